@@ -0,0 +1,245 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    health::CircuitBreaker,
+    hooks::CcipHook,
+    host_options::HostOptions,
+    metrics::MetricsSink,
+    policy::{GatewayPolicy, PublicResolver},
+    rate_limit::RateLimit,
+    retry::RetryPolicy,
+    transport::{GatewayTransport, ReqwestTransport},
+};
+
+/// Default maximum number of `OffchainLookup` hops followed for a single call
+pub const DEFAULT_MAX_REDIRECTS: u8 = 10;
+
+/// Default maximum size of a gateway response body, in bytes
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Default `User-Agent` of gateway requests
+pub const DEFAULT_USER_AGENT: &str = concat!("ethers-ccip-read/", env!("CARGO_PKG_VERSION"));
+
+/// How the URLs of an `OffchainLookup` are fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GatewayStrategy {
+    /// One URL after another, in the order given by the contract
+    #[default]
+    Sequential,
+    /// Every URL at once, the first response wins and the other requests are cancelled
+    Parallel,
+    /// Starts the next URL whenever the running ones have not answered within
+    /// the delay or have failed, the first response wins
+    Hedged(Duration),
+    /// One URL after another, in random order to spread the load
+    Shuffled,
+}
+
+/// Configuration of the CCIP-Read flow of a [`CCIPReadMiddleware`](crate::CCIPReadMiddleware)
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use ethers_ccip_read::{CCIPReadConfig, GatewayStrategy};
+///
+/// let config = CCIPReadConfig::default()
+///     .max_redirects(4)
+///     .request_timeout(Duration::from_secs(2))
+///     .deadline(Duration::from_secs(5))
+///     .max_response_size(64 * 1024)
+///     .strategy(GatewayStrategy::Hedged(Duration::from_millis(300)))
+///     .client(reqwest::Client::new());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CCIPReadConfig {
+    /// Maximum number of `OffchainLookup` hops followed for a single call
+    pub max_redirects: u8,
+    /// Timeout of a single gateway request
+    pub request_timeout: Option<Duration>,
+    /// Deadline for a whole call, including every hop and gateway request, or
+    /// for a whole name resolution, shared by every call it makes
+    pub deadline: Option<Duration>,
+    /// HTTP client used for gateway requests, a default client is created when unset
+    pub client: Option<reqwest::Client>,
+    /// Transport used for gateway requests, a [`ReqwestTransport`] is used when unset
+    pub transport: Option<Arc<dyn GatewayTransport>>,
+    /// Restrictions on the gateway URLs that are fetched
+    pub gateway_policy: GatewayPolicy,
+    /// Maximum size of a gateway response body, in bytes
+    pub max_response_size: usize,
+    /// Retries of failing gateway URLs
+    pub retry: RetryPolicy,
+    /// Order in which gateway URLs are fetched
+    pub strategy: GatewayStrategy,
+    /// Deprioritizes failing gateway hosts, disabled when unset
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Limits the requests per gateway host, unlimited when unset
+    pub rate_limit: Option<RateLimit>,
+    /// `User-Agent` of gateway requests
+    pub user_agent: String,
+    /// Headers and client identities of specific gateway hosts
+    pub hosts: Vec<HostOptions>,
+    /// Called at each step of the CCIP-Read flow, in order
+    pub hooks: Vec<Arc<dyn CcipHook>>,
+    /// Receives the measurements of the CCIP-Read flow and ENS resolution
+    pub metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl Default for CCIPReadConfig {
+    fn default() -> Self {
+        Self {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            request_timeout: None,
+            deadline: None,
+            client: None,
+            transport: None,
+            gateway_policy: GatewayPolicy::default(),
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            retry: RetryPolicy::default(),
+            strategy: GatewayStrategy::default(),
+            circuit_breaker: None,
+            rate_limit: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            hosts: vec![],
+            hooks: vec![],
+            metrics: None,
+        }
+    }
+}
+
+impl CCIPReadConfig {
+    pub fn max_redirects(mut self, max_redirects: u8) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the HTTP client. The redirect limit of the gateway policy and the
+    /// user agent are only applied to the default client, see
+    /// [`GatewayPolicy::redirect_policy`], and so is the [`PublicResolver`]
+    /// blocking private addresses. Hosts with their own [`HostOptions`]
+    /// always use a dedicated client, which does not share the settings of
+    /// this one: custom root certificates must be set on the options too.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn transport<T: GatewayTransport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    pub fn gateway_policy(mut self, policy: GatewayPolicy) -> Self {
+        self.gateway_policy = policy;
+        self
+    }
+
+    pub fn max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = max_response_size;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: GatewayStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn user_agent<T: Into<String>>(mut self, user_agent: T) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Adds headers or a client identity to the requests to the hosts matching `options`
+    pub fn host(mut self, options: HostOptions) -> Self {
+        self.hosts.push(options);
+        self
+    }
+
+    pub fn hook<H: CcipHook + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub fn metrics<S: MetricsSink + 'static>(mut self, metrics: S) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// The configured HTTP client, or a default one following the gateway policy
+    pub(crate) fn build_client(&self) -> Result<reqwest::Client, reqwest::Error> {
+        match &self.client {
+            Some(client) => Ok(client.clone()),
+            None => self
+                .client_builder()
+                .redirect(self.gateway_policy.redirect_policy())
+                .build(),
+        }
+    }
+
+    /// The configured transport, or a [`ReqwestTransport`] using `client`
+    pub(crate) fn build_transport(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<Arc<dyn GatewayTransport>, reqwest::Error> {
+        if let Some(transport) = &self.transport {
+            return Ok(transport.clone());
+        }
+
+        let mut transport =
+            ReqwestTransport::new(client.clone()).max_response_size(self.max_response_size);
+        for options in &self.hosts {
+            transport = transport.host(options.clone(), self.build_host_client(options)?);
+        }
+        Ok(Arc::new(transport))
+    }
+
+    /// The settings shared by the default and per-host clients
+    fn client_builder(&self) -> reqwest::ClientBuilder {
+        let builder = reqwest::Client::builder().user_agent(&self.user_agent);
+        match self.gateway_policy.block_private_ips {
+            true => builder.dns_resolver(Arc::new(PublicResolver)),
+            false => builder,
+        }
+    }
+
+    /// A client that only follows redirects within the hosts of `options`
+    fn build_host_client(&self, options: &HostOptions) -> Result<reqwest::Client, reqwest::Error> {
+        let redirect = self
+            .gateway_policy
+            .redirect_policy_within(Some(options));
+        let mut builder = self.client_builder().redirect(redirect);
+        if let Some(identity) = &options.identity {
+            builder = builder.identity(identity.clone());
+        }
+        for certificate in &options.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder.build()
+    }
+}
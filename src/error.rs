@@ -1,9 +1,41 @@
-use ethers_providers::{JsonRpcError, Middleware};
-use crate::native::ccip_request::{CCIPGatewayErrors};
+use std::time::Duration;
+
+use ethers_core::{
+    abi::{self, ethereum_types::FromDecStrErr, InvalidOutputType},
+    types::{Address, BlockId},
+};
+use ethers_providers::{JsonRpcError, Middleware, MiddlewareError};
+use crate::{
+    native::{call::LookupHops, ccip_request::CCIPGatewayErrors},
+    policy::PolicyViolation,
+    transport::ResponseTooLarge,
+    utils::contract_revert::ContractRevert,
+};
 use thiserror::Error;
 
+/// Errors raised while decoding untrusted call results or revert data
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    /// Thrown when the data is shorter than the expected ABI encoding
+    #[error("Data too short: expected at least {expected} bytes, got {actual}")]
+    ShortData { expected: usize, actual: usize },
+
+    /// Thrown when the data after the selector is not a whole number of 32 byte words
+    #[error("Data of {0} bytes is not aligned to 32 byte words")]
+    MisalignedData(usize),
+
+    #[error(transparent)]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error(transparent)]
+    InvalidAbi(#[from] abi::Error),
+
+    #[error(transparent)]
+    InvalidOutputType(#[from] InvalidOutputType),
+}
+
 #[derive(Error, Debug)]
-pub enum CCIPMiddlewareError<M: Middleware> {
+pub enum CCIPMiddlewareError<M: Middleware + 'static> {
     #[error(transparent)]
     RPCError(#[from] JsonRpcError),
 
@@ -13,13 +45,191 @@ pub enum CCIPMiddlewareError<M: Middleware> {
     #[error(transparent)]
     GatewayError(#[from] CCIPGatewayErrors),
 
+    /// Thrown when an `OffchainLookup` as a whole, or the metadata URL of an
+    /// NFT, is rejected by the gateway policy
+    #[error("Rejected by gateway policy: {0}")]
+    GatewayPolicyError(PolicyViolation),
+
+    /// Thrown when the metadata of an NFT is larger than the maximum response size
+    #[error(transparent)]
+    ResponseTooLargeError(#[from] ResponseTooLarge),
+
     #[error("Max redirection attempts reached")]
     MaxRedirectionError,
 
-    #[error("Todo but Error is {0}")]
-    TodoError(String),
+    /// Thrown when a contract repeats an `OffchainLookup` it already reverted with
+    #[error("OffchainLookup loop detected:\n{0}")]
+    LookupLoopError(LookupHops),
+
+    /// Thrown when a call does not complete within the configured deadline
+    #[error("CCIP-Read deadline of {0:?} exceeded")]
+    DeadlineError(Duration),
+
+    /// Thrown when the `sender` of an `OffchainLookup` is not the contract that was called
+    #[error("OffchainLookup sender {actual:?} does not match the called contract {expected:?}")]
+    SenderError { expected: Address, actual: Address },
+
+    /// Thrown when an `OffchainLookup` did not yield a gateway response
+    #[error("No gateway URL returned a response")]
+    GatewayNotFoundError,
+
+    /// Thrown when a call reverts with anything but an `OffchainLookup`
+    ///
+    /// `response` is the revert as Geth reports it, whatever the node, so that
+    /// `ethers-contract` can decode custom errors out of it.
+    #[error("Execution reverted: {revert}")]
+    ContractRevert {
+        revert: ContractRevert,
+        response: JsonRpcError,
+    },
+
+    /// Thrown when the block of a call can't be pinned to its hash
+    #[error("Block {0:?} not found")]
+    BlockNotFoundError(BlockId),
+
+    #[error(transparent)]
+    DecodeError(#[from] DecodeError),
+
+    /// Thrown when a call to the ENS registry or a resolver fails, wrapping the
+    /// underlying error unchanged as its source
+    #[error("Error calling resolver {resolver:?} for {name}")]
+    ResolverCallError {
+        resolver: Address,
+        name: String,
+        #[source]
+        error: Box<Self>,
+    },
+
+    /// Thrown when a name can't be DNS-encoded for wildcard resolution, e.g. a label over 63 bytes
+    #[error("Invalid name {name}: {reason}")]
+    InvalidNameError { name: String, reason: String },
+
+    /// Thrown when the resolved name of an address does not resolve back to it
+    #[error("Reverse record {name} resolves to {resolved:?} instead of {address:?}")]
+    ReverseRecordError {
+        address: Address,
+        name: String,
+        resolved: Address,
+    },
+
+    /// Thrown when the coin type is not a decimal number
+    #[error("Invalid coin type {coin_type}")]
+    InvalidCoinTypeError {
+        coin_type: String,
+        #[source]
+        source: FromDecStrErr,
+    },
+
+    #[error(transparent)]
+    URLParseError(#[from] url::ParseError),
+
+    /// Thrown when an avatar or NFT image uses a scheme other than https, data, ipfs or eip155
+    #[error("Unsupported URL scheme {0}")]
+    UnsupportedURLSchemeError(String),
+
+    /// Thrown when the avatar NFT is not owned by the address the name resolves to
+    #[error("NFT {contract:?} is not owned by {owner:?}")]
+    NFTOwnerError { contract: Address, owner: Address },
+
+    /// Thrown when an NFT reference, its IPFS link or its metadata is malformed
+    #[error("Invalid NFT: {0}")]
+    NFTError(String),
 
     /// Thrown when the internal middleware errors
     #[error(transparent)]
     MiddlewareError(M::Error),
 }
+
+impl<M: Middleware + 'static> CCIPMiddlewareError<M> {
+    /// A [`ContractRevert`](Self::ContractRevert) error out of revert data
+    pub(crate) fn contract_revert(data: &[u8]) -> Self {
+        let revert = ContractRevert::decode(data);
+        let message = match &revert {
+            ContractRevert::Error(reason) => format!("execution reverted: {}", reason),
+            _ => "execution reverted".to_string(),
+        };
+        let response = JsonRpcError {
+            code: 3,
+            message,
+            data: Some(format!("0x{}", hex::encode(data)).into()),
+        };
+        CCIPMiddlewareError::ContractRevert { revert, response }
+    }
+
+    /// The name of the variant, e.g. to label metrics
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            CCIPMiddlewareError::RPCError(_) => "RPCError",
+            CCIPMiddlewareError::ReqwestError(_) => "ReqwestError",
+            CCIPMiddlewareError::GatewayError(_) => "GatewayError",
+            CCIPMiddlewareError::GatewayPolicyError(_) => "GatewayPolicyError",
+            CCIPMiddlewareError::ResponseTooLargeError(_) => "ResponseTooLargeError",
+            CCIPMiddlewareError::MaxRedirectionError => "MaxRedirectionError",
+            CCIPMiddlewareError::LookupLoopError(_) => "LookupLoopError",
+            CCIPMiddlewareError::DeadlineError(_) => "DeadlineError",
+            CCIPMiddlewareError::SenderError { .. } => "SenderError",
+            CCIPMiddlewareError::GatewayNotFoundError => "GatewayNotFoundError",
+            CCIPMiddlewareError::ContractRevert { .. } => "ContractRevert",
+            CCIPMiddlewareError::BlockNotFoundError(_) => "BlockNotFoundError",
+            CCIPMiddlewareError::DecodeError(_) => "DecodeError",
+            CCIPMiddlewareError::ResolverCallError { .. } => "ResolverCallError",
+            CCIPMiddlewareError::InvalidNameError { .. } => "InvalidNameError",
+            CCIPMiddlewareError::ReverseRecordError { .. } => "ReverseRecordError",
+            CCIPMiddlewareError::InvalidCoinTypeError { .. } => "InvalidCoinTypeError",
+            CCIPMiddlewareError::URLParseError(_) => "URLParseError",
+            CCIPMiddlewareError::UnsupportedURLSchemeError(_) => "UnsupportedURLSchemeError",
+            CCIPMiddlewareError::NFTOwnerError { .. } => "NFTOwnerError",
+            CCIPMiddlewareError::NFTError(_) => "NFTError",
+            CCIPMiddlewareError::MiddlewareError(_) => "MiddlewareError",
+        }
+    }
+}
+
+impl<M: Middleware + 'static> MiddlewareError for CCIPMiddlewareError<M> {
+    type Inner = M::Error;
+
+    fn from_err(src: M::Error) -> Self {
+        CCIPMiddlewareError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            CCIPMiddlewareError::MiddlewareError(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            CCIPMiddlewareError::RPCError(e) => Some(e),
+            CCIPMiddlewareError::ContractRevert { response, .. } => Some(response),
+            _ => self.as_inner()?.as_error_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use ethers_providers::{MockProvider, Provider};
+
+    use super::*;
+
+    #[test]
+    fn test_resolver_call_error_source() {
+        let error = CCIPMiddlewareError::<Provider<MockProvider>>::ResolverCallError {
+            resolver: Address::repeat_byte(1),
+            name: "nick.eth".to_string(),
+            error: Box::new(CCIPMiddlewareError::contract_revert(&[])),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Error calling resolver 0x0101010101010101010101010101010101010101 for nick.eth"
+        );
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "Execution reverted: no revert data");
+        assert!(source.source().is_none());
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use url::Url;
+
+/// Weight of the newest sample in the average latency
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// Most hosts tracked at once, the least recently requested one being
+/// forgotten to make room for a new one
+const MAX_HOSTS: usize = 1024;
+
+/// Stops sending requests first to gateway hosts that keep failing
+///
+/// After `failure_threshold` consecutive failures a host is open for
+/// `cooldown`: its URLs are moved after the other URLs of an `OffchainLookup`,
+/// or skipped altogether with [`CircuitBreaker::skip_open_hosts`]. Once the
+/// cooldown is over the host is half-open, the next request probes it: a
+/// success closes it again, a failure opens it for another cooldown. Other
+/// requests treat the host as open while the probe is running.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use ethers_ccip_read::{health::CircuitBreaker, CCIPReadConfig};
+///
+/// let config = CCIPReadConfig::default().circuit_breaker(
+///     CircuitBreaker::default()
+///         .failure_threshold(3)
+///         .cooldown(Duration::from_secs(60)),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Consecutive failures after which a host is opened
+    pub failure_threshold: u32,
+    /// How long a host stays open before being probed again
+    pub cooldown: Duration,
+    /// Skip the URLs of open hosts instead of trying them last
+    pub skip_open_hosts: bool,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            skip_open_hosts: false,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn skip_open_hosts(mut self, skip_open_hosts: bool) -> Self {
+        self.skip_open_hosts = skip_open_hosts;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The host is healthy
+    Closed,
+    /// The host keeps failing and is in its cooldown
+    Open,
+    /// The cooldown is over, the next request probes whether the host is healthy again
+    HalfOpen,
+}
+
+/// Health of a single gateway host, see [`CCIPReadMiddleware::gateway_health`](crate::CCIPReadMiddleware::gateway_health)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostHealth {
+    /// Host of the gateway URLs, including the port when not the default one
+    pub host: String,
+    pub successes: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    /// Moving average of the response time
+    pub average_latency: Option<Duration>,
+    pub state: CircuitState,
+}
+
+impl HostHealth {
+    /// Share of successful requests, `None` before the first request
+    pub fn success_rate(&self) -> Option<f64> {
+        let total = self.successes + self.failures;
+        (total > 0).then(|| self.successes as f64 / total as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct HostStats {
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    average_latency: Option<Duration>,
+    opened_at: Option<Instant>,
+    /// Set while a request probes the half-open host
+    probing_since: Option<Instant>,
+    /// Value of the request counter of the tracker at the last request
+    last_request: u64,
+}
+
+impl HostStats {
+    fn record_latency(&mut self, latency: Duration) {
+        self.average_latency = Some(match self.average_latency {
+            Some(average) => average.mul_f64(1.0 - LATENCY_WEIGHT) + latency.mul_f64(LATENCY_WEIGHT),
+            None => latency,
+        });
+    }
+}
+
+/// Success rate, latency and circuit state of the gateway hosts, shared by
+/// the clones of a middleware
+///
+/// Gateway URLs come from contract revert data, so the number of hosts is
+/// bounded by [`MAX_HOSTS`].
+#[derive(Debug, Default)]
+pub(crate) struct HealthTracker {
+    breaker: Option<CircuitBreaker>,
+    hosts: Mutex<HashMap<String, HostStats>>,
+    requests: AtomicU64,
+}
+
+impl HealthTracker {
+    pub(crate) fn new(breaker: Option<CircuitBreaker>) -> Self {
+        Self {
+            breaker,
+            hosts: Mutex::default(),
+            requests: AtomicU64::default(),
+        }
+    }
+
+    /// The key of `url` in the tracker, its host and non-default port
+    pub(crate) fn host(url: &str) -> String {
+        match Url::parse(url) {
+            Ok(parsed) => match (parsed.host_str(), parsed.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (Some(host), None) => host.to_string(),
+                (None, _) => url.to_string(),
+            },
+            Err(_) => url.to_string(),
+        }
+    }
+
+    pub(crate) fn state(&self, host: &str) -> CircuitState {
+        let hosts = self.hosts.lock().unwrap();
+        self.state_of(hosts.get(host))
+    }
+
+    /// Whether requests to `host` must be skipped
+    ///
+    /// A request let through to a half-open host is its probe: the host is
+    /// open for the other requests until the probe is recorded, or for at most
+    /// a cooldown if it never is.
+    pub(crate) fn skip(&self, host: &str) -> bool {
+        let breaker = match &self.breaker {
+            Some(breaker) => breaker,
+            None => return false,
+        };
+        let mut hosts = self.hosts.lock().unwrap();
+        match self.state_of(hosts.get(host)) {
+            CircuitState::Closed => false,
+            CircuitState::Open => breaker.skip_open_hosts,
+            CircuitState::HalfOpen => {
+                if let Some(stats) = hosts.get_mut(host) {
+                    stats.probing_since = Some(Instant::now());
+                }
+                false
+            }
+        }
+    }
+
+    fn state_of(&self, stats: Option<&HostStats>) -> CircuitState {
+        let (breaker, stats) = match (&self.breaker, stats) {
+            (Some(breaker), Some(stats)) => (breaker, stats),
+            _ => return CircuitState::Closed,
+        };
+        let within_cooldown = |since: Option<Instant>| {
+            since.is_some_and(|since| since.elapsed() < breaker.cooldown)
+        };
+        match stats.opened_at {
+            Some(_) if within_cooldown(stats.opened_at) || within_cooldown(stats.probing_since) => {
+                CircuitState::Open
+            }
+            Some(_) => CircuitState::HalfOpen,
+            None => CircuitState::Closed,
+        }
+    }
+
+    pub(crate) fn record_success(&self, host: &str, latency: Duration) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = self.entry(&mut hosts, host);
+        stats.successes += 1;
+        stats.consecutive_failures = 0;
+        stats.opened_at = None;
+        stats.probing_since = None;
+        stats.record_latency(latency);
+    }
+
+    /// Records a failed request, `latency` being set if the gateway responded
+    pub(crate) fn record_failure(&self, host: &str, latency: Option<Duration>) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let half_open = self.state_of(hosts.get(host)) == CircuitState::HalfOpen;
+        let stats = self.entry(&mut hosts, host);
+        let probe = stats.probing_since.take().is_some();
+        stats.failures += 1;
+        stats.consecutive_failures += 1;
+        if let Some(latency) = latency {
+            stats.record_latency(latency);
+        }
+
+        if let Some(breaker) = &self.breaker {
+            if half_open || probe || stats.consecutive_failures >= breaker.failure_threshold {
+                stats.opened_at = Some(Instant::now());
+            }
+        }
+    }
+
+    /// The stats of `host`, making room for it if it is new
+    fn entry<'a>(
+        &self,
+        hosts: &'a mut HashMap<String, HostStats>,
+        host: &str,
+    ) -> &'a mut HostStats {
+        if !hosts.contains_key(host) && hosts.len() >= MAX_HOSTS {
+            let oldest = hosts
+                .iter()
+                .min_by_key(|(_, stats)| stats.last_request)
+                .map(|(host, _)| host.clone());
+            if let Some(oldest) = oldest {
+                hosts.remove(&oldest);
+            }
+        }
+        let stats = hosts.entry(host.to_string()).or_default();
+        stats.last_request = self.requests.fetch_add(1, Ordering::Relaxed);
+        stats
+    }
+
+    /// The health of every host that was requested, sorted by host
+    pub(crate) fn snapshot(&self) -> Vec<HostHealth> {
+        let hosts = self.hosts.lock().unwrap();
+        let mut snapshot: Vec<_> = hosts
+            .iter()
+            .map(|(host, stats)| HostHealth {
+                host: host.clone(),
+                successes: stats.successes,
+                failures: stats.failures,
+                consecutive_failures: stats.consecutive_failures,
+                average_latency: stats.average_latency,
+                state: self.state_of(Some(stats)),
+            })
+            .collect();
+        snapshot.sort_by(|a, b| a.host.cmp(&b.host));
+        snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        assert_eq!(HealthTracker::host("https://gateway.example/{sender}"), "gateway.example");
+        assert_eq!(HealthTracker::host("https://gateway.example:443/"), "gateway.example");
+        assert_eq!(HealthTracker::host("http://127.0.0.1:8000/"), "127.0.0.1:8000");
+        assert_eq!(HealthTracker::host("not a url"), "not a url");
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::default()
+            .failure_threshold(2)
+            .cooldown(Duration::from_millis(50));
+        let tracker = HealthTracker::new(Some(breaker));
+        let host = "gateway.example";
+
+        tracker.record_failure(host, None);
+        assert_eq!(tracker.state(host), CircuitState::Closed);
+        tracker.record_failure(host, Some(Duration::from_millis(10)));
+        assert_eq!(tracker.state(host), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(tracker.state(host), CircuitState::HalfOpen);
+
+        // a failed probe opens the host again right away
+        tracker.record_failure(host, None);
+        assert_eq!(tracker.state(host), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        tracker.record_success(host, Duration::from_millis(20));
+        assert_eq!(tracker.state(host), CircuitState::Closed);
+
+        let health = &tracker.snapshot()[0];
+        assert_eq!(health.successes, 1);
+        assert_eq!(health.failures, 3);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.success_rate(), Some(0.25));
+        let latency = health.average_latency.unwrap();
+        assert!(latency > Duration::from_micros(11_999) && latency < Duration::from_micros(12_001));
+    }
+
+    #[test]
+    fn test_single_probe() {
+        let breaker = CircuitBreaker::default()
+            .failure_threshold(1)
+            .cooldown(Duration::from_millis(50))
+            .skip_open_hosts(true);
+        let tracker = HealthTracker::new(Some(breaker));
+        let host = "gateway.example";
+
+        tracker.record_failure(host, None);
+        assert!(tracker.skip(host));
+        std::thread::sleep(Duration::from_millis(60));
+
+        // only the first request probes the host
+        assert!(!tracker.skip(host));
+        assert!(tracker.skip(host));
+        assert_eq!(tracker.state(host), CircuitState::Open);
+
+        tracker.record_success(host, Duration::from_millis(10));
+        assert!(!tracker.skip(host));
+        assert_eq!(tracker.state(host), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_tracking_without_breaker() {
+        let tracker = HealthTracker::new(None);
+
+        for _ in 0..10 {
+            tracker.record_failure("gateway.example", None);
+        }
+
+        assert_eq!(tracker.state("gateway.example"), CircuitState::Closed);
+        assert!(!tracker.skip("gateway.example"));
+        assert_eq!(tracker.snapshot()[0].failures, 10);
+    }
+
+    #[test]
+    fn test_least_recent_host_is_forgotten() {
+        let tracker = HealthTracker::new(None);
+
+        for host in 0..MAX_HOSTS {
+            tracker.record_success(&format!("{}.example", host), Duration::ZERO);
+        }
+        tracker.record_failure("0.example", None);
+        tracker.record_success("new.example", Duration::ZERO);
+
+        let hosts: Vec<_> = tracker.snapshot().into_iter().map(|health| health.host).collect();
+        assert_eq!(hosts.len(), MAX_HOSTS);
+        assert!(hosts.contains(&"0.example".to_string()));
+        assert!(hosts.contains(&"new.example".to_string()));
+        assert!(!hosts.contains(&"1.example".to_string()));
+    }
+}
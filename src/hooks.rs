@@ -0,0 +1,245 @@
+use std::{error::Error, fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, Bytes};
+
+use crate::{native::ccip_request::CCIPRequestError, utils::offchain_lookup::OffchainLookup};
+
+/// What to do with a gateway URL, decided by [`CcipHook::before_request`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestAction {
+    /// Request the URL as is
+    Continue,
+    /// Request another URL instead, e.g. an internal mirror
+    Rewrite(String),
+    /// Skip the URL and try the next one
+    Skip(String),
+    /// Skip the URL and end the lookup
+    Abort(String),
+}
+
+/// Callbacks at each step of the CCIP-Read flow, for audit logging, URL
+/// rewriting or custom policies
+///
+/// Every method does nothing by default. Hooks are added with
+/// [`CCIPReadConfig::hook`](crate::CCIPReadConfig::hook) and called in the
+/// order they were added.
+///
+/// # Example
+///
+/// ```
+/// use async_trait::async_trait;
+/// use ethers_ccip_read::{
+///     hooks::{CcipHook, RequestAction},
+///     CCIPReadConfig,
+/// };
+/// use ethers_core::types::Address;
+///
+/// #[derive(Debug)]
+/// struct Mirror;
+///
+/// #[async_trait]
+/// impl CcipHook for Mirror {
+///     async fn before_request(&self, _sender: Address, url: &str) -> RequestAction {
+///         match url.strip_prefix("https://gateway.example/") {
+///             Some(path) => RequestAction::Rewrite(format!("https://mirror.internal/{}", path)),
+///             None => RequestAction::Continue,
+///         }
+///     }
+/// }
+///
+/// let config = CCIPReadConfig::default().hook(Mirror);
+/// ```
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait CcipHook: Debug + Send + Sync {
+    /// Called when a call reverted with an `OffchainLookup`, before any gateway request
+    async fn on_lookup(&self, _tx: &TypedTransaction, _lookup: &OffchainLookup) {}
+
+    /// Called once before the requests to each gateway URL, including retries
+    async fn before_request(&self, _sender: Address, _url: &str) -> RequestAction {
+        RequestAction::Continue
+    }
+
+    /// Called after every request to a gateway URL, with the data or the error of the response
+    async fn after_response(&self, _url: &str, _result: Result<&Bytes, &CCIPRequestError>) {}
+
+    /// Called before `callback_tx`, calling the callback function with the gateway response
+    async fn before_callback(&self, _callback_tx: &TypedTransaction, _lookup: &OffchainLookup) {}
+
+    /// Called with the final result of a call, after every `OffchainLookup` was followed
+    ///
+    /// Only the calls made through [`call`](crate::CCIPReadMiddleware::call) or
+    /// [`handle_offchain_lookup`](crate::CCIPReadMiddleware::handle_offchain_lookup)
+    /// are reported, not the ones made by ENS resolution, such as
+    /// `supportsInterface` probes whose failure is expected.
+    async fn on_result(
+        &self,
+        _tx: &TypedTransaction,
+        _result: Result<&Bytes, &(dyn Error + Send + Sync)>,
+    ) {
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T: CcipHook + ?Sized> CcipHook for Arc<T> {
+    async fn on_lookup(&self, tx: &TypedTransaction, lookup: &OffchainLookup) {
+        self.as_ref().on_lookup(tx, lookup).await
+    }
+
+    async fn before_request(&self, sender: Address, url: &str) -> RequestAction {
+        self.as_ref().before_request(sender, url).await
+    }
+
+    async fn after_response(&self, url: &str, result: Result<&Bytes, &CCIPRequestError>) {
+        self.as_ref().after_response(url, result).await
+    }
+
+    async fn before_callback(&self, callback_tx: &TypedTransaction, lookup: &OffchainLookup) {
+        self.as_ref().before_callback(callback_tx, lookup).await
+    }
+
+    async fn on_result(
+        &self,
+        tx: &TypedTransaction,
+        result: Result<&Bytes, &(dyn Error + Send + Sync)>,
+    ) {
+        self.as_ref().on_result(tx, result).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use ethers_core::types::H256;
+    use ethers_providers::{MockProvider, Provider};
+
+    use super::*;
+    use crate::{
+        error::CCIPMiddlewareError,
+        native::ccip_request::CCIPRequestErrorKind,
+        test_utils::{lookup_via, push_responses, revert, success, CannedResponse, TestGateway},
+        CCIPReadConfig, CCIPReadMiddleware,
+    };
+
+    #[derive(Debug, Default)]
+    struct RecordingHook {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingHook {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[async_trait]
+    impl CcipHook for RecordingHook {
+        async fn on_lookup(&self, _tx: &TypedTransaction, lookup: &OffchainLookup) {
+            self.record(format!("lookup {}", lookup.urls.len()));
+        }
+
+        async fn before_request(&self, _sender: Address, url: &str) -> RequestAction {
+            self.record("request".to_string());
+            if url.ends_with("/skip") {
+                RequestAction::Skip("skipped".to_string())
+            } else if url.ends_with("/abort") {
+                RequestAction::Abort("aborted".to_string())
+            } else {
+                RequestAction::Rewrite(url.replace("/old", "/new"))
+            }
+        }
+
+        async fn after_response(&self, url: &str, result: Result<&Bytes, &CCIPRequestError>) {
+            self.record(format!("response {} {}", url.rsplit('/').next().unwrap(), result.is_ok()));
+        }
+
+        async fn before_callback(&self, _callback_tx: &TypedTransaction, _lookup: &OffchainLookup) {
+            self.record("callback".to_string());
+        }
+
+        async fn on_result(
+            &self,
+            _tx: &TypedTransaction,
+            result: Result<&Bytes, &(dyn Error + Send + Sync)>,
+        ) {
+            self.record(format!("result {}", result.is_ok()));
+        }
+    }
+
+    async fn lookup_with_hook(
+        gateway: &TestGateway,
+        paths: &[&str],
+    ) -> (Result<Bytes, CCIPMiddlewareError<Provider<MockProvider>>>, Vec<String>) {
+        let (provider, mock) = Provider::mocked();
+        let hook = Arc::new(RecordingHook::default());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().hook(hook.clone()));
+
+        let urls = paths.iter().map(|path| gateway.url(path)).collect();
+        let result = lookup_via(&middleware, &mock, urls).await;
+        let events = hook.events.lock().unwrap().clone();
+        (result, events)
+    }
+
+    #[tokio::test]
+    async fn test_hooks_are_called() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/new" => CannedResponse::data(&[0xca, 0xfe]),
+            _ => CannedResponse::new(404, "not found"),
+        })
+        .await;
+
+        let (result, events) = lookup_with_hook(&gateway, &["/skip", "/old"]).await;
+
+        assert_eq!(result.unwrap(), Bytes::from(vec![1]));
+        assert_eq!(gateway.requests()[0].path, "/new");
+        assert_eq!(
+            events,
+            vec![
+                "lookup 2",
+                "request",
+                "request",
+                "response new true",
+                "callback",
+                "result true"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_result_for_resolution_calls() {
+        let (provider, mock) = Provider::mocked();
+        let hook = Arc::new(RecordingHook::default());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().hook(hook.clone()));
+        let resolver = H256::from(Address::repeat_byte(1));
+        let address = Address::repeat_byte(2);
+        push_responses(
+            &mock,
+            vec![success(resolver.as_bytes()), revert(&[]), success(H256::from(address).as_bytes())],
+        );
+
+        assert_eq!(middleware.resolve_name("nick.eth").await.unwrap(), address);
+        assert!(hook.events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hook_aborts_lookup() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+
+        let (result, events) = lookup_with_hook(&gateway, &["/abort", "/old"]).await;
+
+        assert!(gateway.requests().is_empty());
+        assert_eq!(events, vec!["lookup 2", "request", "result false"]);
+        match result.unwrap_err() {
+            CCIPMiddlewareError::GatewayError(errors) => assert!(matches!(
+                &errors.errors()[0].kind,
+                CCIPRequestErrorKind::AbortedByHook(reason) if reason == "aborted"
+            )),
+            other => panic!("expected gateway errors, got {:?}", other),
+        }
+    }
+}
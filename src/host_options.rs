@@ -0,0 +1,190 @@
+use std::{fmt, sync::Arc};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, IntoHeaderName, AUTHORIZATION},
+    Certificate, Identity, Url,
+};
+
+use crate::policy::host_matches;
+
+/// Adds headers to a request, given its final URL
+pub type HeaderFn = dyn Fn(&Url, &mut HeaderMap) + Send + Sync;
+
+/// Headers and TLS client identity for the gateways of matching hosts
+///
+/// `pattern` is a host name, or `*.example.com` for any subdomain of
+/// `example.com`. Only `https` requests to a matching host get the headers and
+/// identity, and their redirects are only followed to `https` URLs of matching
+/// hosts, so that credentials never reach another host or go out in the clear.
+/// [`HostOptions::allow_http`] lifts the `https` requirement. When several
+/// options match a host, the first one is used.
+///
+/// Requests to matching hosts go through a client of their own, built with
+/// the user agent, the gateway policy and the TLS settings of these options:
+/// the client set by [`CCIPReadConfig::client`](crate::CCIPReadConfig::client)
+/// is not used for them, so its root certificates must be added here too. An
+/// identity or certificate the TLS backend rejects makes building these clients
+/// fail, see [`CCIPReadMiddleware::try_with_config`](crate::CCIPReadMiddleware::try_with_config).
+///
+/// Header values are hidden from the `Debug` output.
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::{host_options::HostOptions, CCIPReadConfig};
+/// use reqwest::header::HeaderValue;
+///
+/// let config = CCIPReadConfig::default()
+///     .user_agent("my-app/1.0")
+///     .host(
+///         HostOptions::new("gateway.partner.example")
+///             .header("x-api-key", HeaderValue::from_static("secret")),
+///     )
+///     .host(HostOptions::new("*.internal.example").bearer_auth("token"));
+/// ```
+#[derive(Clone)]
+pub struct HostOptions {
+    pub pattern: String,
+    pub headers: HeaderMap,
+    pub header_fn: Option<Arc<HeaderFn>>,
+    /// Client certificate for mutual TLS
+    pub identity: Option<Identity>,
+    /// Root certificates trusted on top of the system ones
+    pub root_certificates: Vec<Certificate>,
+    /// Also apply to `http` URLs
+    pub allow_http: bool,
+}
+
+impl HostOptions {
+    pub fn new<T: Into<String>>(pattern: T) -> Self {
+        Self {
+            pattern: pattern.into().to_ascii_lowercase(),
+            headers: HeaderMap::new(),
+            header_fn: None,
+            identity: None,
+            root_certificates: vec![],
+            allow_http: false,
+        }
+    }
+
+    /// Adds a header, marked as sensitive
+    pub fn header<K: IntoHeaderName>(mut self, name: K, mut value: HeaderValue) -> Self {
+        value.set_sensitive(true);
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Sets an `Authorization: Bearer` header
+    ///
+    /// # Panics
+    ///
+    /// If `token` is not a valid header value
+    pub fn bearer_auth(self, token: &str) -> Self {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .expect("bearer token is not a valid header value");
+        self.header(AUTHORIZATION, value)
+    }
+
+    /// Sets a callback adding headers to every request, e.g. short-lived tokens
+    pub fn header_fn<F>(mut self, header_fn: F) -> Self
+    where
+        F: Fn(&Url, &mut HeaderMap) + Send + Sync + 'static,
+    {
+        self.header_fn = Some(Arc::new(header_fn));
+        self
+    }
+
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    pub fn root_certificate(mut self, certificate: Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Also applies the options to `http` URLs, sending the headers in the
+    /// clear, e.g. for a gateway on a private network
+    pub fn allow_http(mut self, allow_http: bool) -> Self {
+        self.allow_http = allow_http;
+        self
+    }
+
+    /// Whether the options apply to `host`
+    pub fn matches(&self, host: &str) -> bool {
+        host_matches(&self.pattern, &host.to_ascii_lowercase())
+    }
+
+    /// Whether the options apply to `url`, by its scheme and host
+    pub fn applies_to(&self, url: &Url) -> bool {
+        let scheme_allowed = match url.scheme() {
+            "https" => true,
+            "http" => self.allow_http,
+            _ => false,
+        };
+        scheme_allowed && self.matches(url.host_str().unwrap_or_default())
+    }
+
+    /// The headers of a request to `url`
+    pub(crate) fn headers_for(&self, url: &Url) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        if let Some(header_fn) = &self.header_fn {
+            header_fn(url, &mut headers);
+        }
+        headers
+    }
+}
+
+impl fmt::Debug for HostOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostOptions")
+            .field("pattern", &self.pattern)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("header_fn", &self.header_fn.is_some())
+            .field("identity", &self.identity.is_some())
+            .field("root_certificates", &self.root_certificates.len())
+            .field("allow_http", &self.allow_http)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_for() {
+        let options = HostOptions::new("*.Example.com")
+            .bearer_auth("secret")
+            .header_fn(|url, headers| {
+                let path = HeaderValue::from_str(url.path()).unwrap();
+                headers.insert("x-path", path);
+            });
+        let headers = options.headers_for(&Url::parse("https://a.example.com/lookup").unwrap());
+
+        assert!(options.matches("a.EXAMPLE.com"));
+        assert!(!options.matches("example.com"));
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+        assert_eq!(headers["x-path"], "/lookup");
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let url = |url| Url::parse(url).unwrap();
+        let options = HostOptions::new("gateway.example");
+
+        assert!(options.applies_to(&url("https://gateway.example/")));
+        assert!(!options.applies_to(&url("http://gateway.example/")));
+        assert!(!options.applies_to(&url("https://other.example/")));
+        assert!(options.allow_http(true).applies_to(&url("http://gateway.example/")));
+    }
+
+    #[test]
+    fn test_debug_hides_credentials() {
+        let options = HostOptions::new("gateway.example").bearer_auth("secret");
+
+        assert!(!format!("{:?}", options).contains("secret"));
+    }
+}
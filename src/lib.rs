@@ -4,8 +4,39 @@
 mod middleware;
 pub use middleware::CCIPReadMiddleware;
 
+mod config;
+pub use config::{
+    CCIPReadConfig, GatewayStrategy, DEFAULT_MAX_REDIRECTS, DEFAULT_MAX_RESPONSE_SIZE,
+    DEFAULT_USER_AGENT,
+};
+
+pub mod policy;
+
+pub mod health;
+
+pub mod hooks;
+
+pub mod host_options;
+
+pub mod metrics;
+
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+pub mod rate_limit;
+
+pub mod retry;
+
+pub mod transport;
+
+#[cfg(feature = "otel")]
+pub mod otel;
+
 pub mod utils;
 
 pub mod native;
 
 pub mod error;
+
+#[cfg(test)]
+mod test_utils;
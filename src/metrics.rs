@@ -0,0 +1,89 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+/// Receives the measurements of the CCIP-Read flow and ENS resolution
+///
+/// Every method does nothing by default and is called synchronously, so
+/// implementations should only update counters. The sink is set with
+/// [`CCIPReadConfig::metrics`](crate::CCIPReadConfig::metrics);
+/// `prometheus::PrometheusMetrics` is available with the `prometheus` feature.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use ethers_ccip_read::{metrics::MetricsSink, CCIPReadConfig};
+///
+/// #[derive(Debug, Default)]
+/// struct GatewayRequests(AtomicU64);
+///
+/// impl MetricsSink for GatewayRequests {
+///     fn gateway_request(&self, _host: &str, _status: Option<u16>, _latency: std::time::Duration) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let config = CCIPReadConfig::default().metrics(GatewayRequests::default());
+/// ```
+pub trait MetricsSink: Debug + Send + Sync {
+    /// A call returned after following `hops` `OffchainLookup` reverts
+    fn lookup_hops(&self, _hops: u8) {}
+
+    /// A request to a gateway `host`, `status` being `None` when it did not respond
+    fn gateway_request(&self, _host: &str, _status: Option<u16>, _latency: Duration) {}
+
+    /// A call to the callback function of an `OffchainLookup`
+    fn callback(&self, _latency: Duration) {}
+
+    /// A resolver lookup in the ENS registry, `found` unless the name has no resolver
+    fn resolver_lookup(&self, _found: bool) {}
+
+    /// A check whether a resolver supports wildcard resolution (ENSIP-10)
+    fn wildcard_check(&self, _supported: bool) {}
+
+    /// A lookup in the cache named `cache`, for caches built on top of the middleware
+    fn cache(&self, _cache: &str, _hit: bool) {}
+
+    /// An `operation` of the middleware, such as `call` or `resolve_name`,
+    /// failed with the `error` variant of
+    /// [`CCIPMiddlewareError`](crate::error::CCIPMiddlewareError)
+    ///
+    /// Only the method called is counted, not the calls it makes itself: a
+    /// failed `resolve_name` counts once, as `resolve_name`.
+    fn error(&self, _operation: &'static str, _error: &'static str) {}
+}
+
+impl<T: MetricsSink + ?Sized> MetricsSink for Arc<T> {
+    fn lookup_hops(&self, hops: u8) {
+        self.as_ref().lookup_hops(hops)
+    }
+
+    fn gateway_request(&self, host: &str, status: Option<u16>, latency: Duration) {
+        self.as_ref().gateway_request(host, status, latency)
+    }
+
+    fn callback(&self, latency: Duration) {
+        self.as_ref().callback(latency)
+    }
+
+    fn resolver_lookup(&self, found: bool) {
+        self.as_ref().resolver_lookup(found)
+    }
+
+    fn wildcard_check(&self, supported: bool) {
+        self.as_ref().wildcard_check(supported)
+    }
+
+    fn cache(&self, cache: &str, hit: bool) {
+        self.as_ref().cache(cache, hit)
+    }
+
+    fn error(&self, operation: &'static str, error: &'static str) {
+        self.as_ref().error(operation, error)
+    }
+}
+
+/// The sink used when none is configured
+#[derive(Debug)]
+pub(crate) struct NoMetrics;
+
+impl MetricsSink for NoMetrics {}
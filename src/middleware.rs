@@ -1,5 +1,18 @@
-use ethers_core::types::Address;
-use ethers_providers::Middleware;
+use std::{future::Future, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, BlockId, Bytes};
+use ethers_providers::{erc, Middleware};
+use reqwest::Url;
+
+use crate::{
+    error::CCIPMiddlewareError,
+    health::{HealthTracker, HostHealth},
+    metrics::{MetricsSink, NoMetrics},
+    rate_limit::RateLimiter,
+    transport::GatewayTransport,
+    CCIPReadConfig,
+};
 
 #[derive(Debug, Clone)]
 pub struct CCIPReadMiddleware<M>
@@ -8,6 +21,13 @@ where
 {
     inner: M,
     pub ens: Option<Address>,
+    config: CCIPReadConfig,
+    /// Shared by all gateway requests, clones of the middleware share its connection pool
+    client: Arc<reqwest::Client>,
+    transport: Arc<dyn GatewayTransport>,
+    /// Shared by clones of the middleware, like the client
+    health: Arc<HealthTracker>,
+    rate_limiter: Arc<RateLimiter>,
 }
 
 impl<M> CCIPReadMiddleware<M>
@@ -15,7 +35,21 @@ where
     M: Middleware,
 {
     pub fn new(inner: M) -> Self {
-        Self { inner, ens: None }
+        Self::from_config(inner, CCIPReadConfig::default())
+            .expect("could not build the default HTTP client")
+    }
+
+    fn from_config(inner: M, config: CCIPReadConfig) -> Result<Self, reqwest::Error> {
+        let client = config.build_client()?;
+        Ok(Self {
+            inner,
+            ens: None,
+            transport: config.build_transport(&client)?,
+            client: Arc::new(client),
+            health: Arc::new(HealthTracker::new(config.circuit_breaker.clone())),
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limit.clone())),
+            config,
+        })
     }
 
     pub fn ens<T: Into<Address>>(mut self, ens: T) -> Self {
@@ -23,8 +57,274 @@ where
         self
     }
 
+    /// Sets the configuration, building its HTTP clients
+    ///
+    /// A transport set with [`with_transport`](Self::with_transport) is kept,
+    /// unless `config` has a transport of its own.
+    ///
+    /// # Panics
+    ///
+    /// If an HTTP client can't be built, e.g. with an invalid identity or root
+    /// certificate in [`HostOptions`](crate::host_options::HostOptions). Use
+    /// [`try_with_config`](Self::try_with_config) to handle the error instead.
+    pub fn with_config(self, config: CCIPReadConfig) -> Self {
+        self.try_with_config(config)
+            .expect("could not build the HTTP clients")
+    }
+
+    /// Same as [`with_config`](Self::with_config), returning the error of
+    /// building the HTTP clients instead of panicking
+    pub fn try_with_config(self, mut config: CCIPReadConfig) -> Result<Self, reqwest::Error> {
+        if config.transport.is_none() {
+            config.transport = self.config.transport;
+        }
+        Ok(Self {
+            ens: self.ens,
+            ..Self::from_config(self.inner, config)?
+        })
+    }
+
+    /// Sets the transport used for gateway requests
+    pub fn with_transport<T: GatewayTransport + 'static>(mut self, transport: T) -> Self {
+        let transport: Arc<dyn GatewayTransport> = Arc::new(transport);
+        self.config.transport = Some(transport.clone());
+        self.transport = transport;
+        self
+    }
+
+    /// Get a reference to the transport used for gateway requests
+    pub fn transport(&self) -> &dyn GatewayTransport {
+        self.transport.as_ref()
+    }
+
+    /// Get a reference to the CCIP-Read configuration
+    pub fn config(&self) -> &CCIPReadConfig {
+        &self.config
+    }
+
+    /// Get a reference to the HTTP client used for NFT metadata and the default transport
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
     /// Get a reference to the inner middleware
     pub fn inner(&self) -> &M {
         &self.inner
     }
+
+    /// The success rate, latency and circuit state of every gateway host requested so far
+    pub fn gateway_health(&self) -> Vec<HostHealth> {
+        self.health.snapshot()
+    }
+
+    pub(crate) fn health(&self) -> &HealthTracker {
+        &self.health
+    }
+
+    pub(crate) fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    pub(crate) fn metrics(&self) -> &dyn MetricsSink {
+        self.config.metrics.as_deref().unwrap_or(&NoMetrics)
+    }
+
+    /// Runs `operation` within the configured deadline, handing it the instant
+    /// by which every call it makes must be done
+    ///
+    /// Called once by each public method, so that a name resolution making
+    /// several calls shares a single deadline.
+    pub(crate) async fn within_deadline<T, F, Fut>(
+        &self,
+        operation: F,
+    ) -> Result<T, CCIPMiddlewareError<M>>
+    where
+        M: 'static,
+        F: FnOnce(Option<Instant>) -> Fut,
+        Fut: Future<Output = Result<T, CCIPMiddlewareError<M>>>,
+    {
+        match self.config.deadline {
+            Some(deadline) => {
+                let operation = operation(Some(Instant::now() + deadline));
+                tokio::time::timeout(deadline, operation)
+                    .await
+                    .unwrap_or(Err(CCIPMiddlewareError::DeadlineError(deadline)))
+            }
+            None => operation(None).await,
+        }
+    }
+
+    /// Counts the error of a failed `operation`
+    pub(crate) fn observe<T>(
+        &self,
+        operation: &'static str,
+        result: Result<T, CCIPMiddlewareError<M>>,
+    ) -> Result<T, CCIPMiddlewareError<M>> {
+        if let Err(error) = &result {
+            self.metrics().error(operation, error.variant_name());
+        }
+        result
+    }
+}
+
+/// Every method not overridden here is forwarded to the inner middleware. The
+/// overridden ones go through the CCIP-Read flow, so any contract call made
+/// through this middleware transparently follows `OffchainLookup` reverts.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<M> Middleware for CCIPReadMiddleware<M>
+where
+    M: Middleware + 'static,
+{
+    type Error = CCIPMiddlewareError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        CCIPReadMiddleware::call(self, tx, block).await
+    }
+
+    async fn resolve_name(&self, ens_name: &str) -> Result<Address, Self::Error> {
+        CCIPReadMiddleware::resolve_name(self, ens_name).await
+    }
+
+    async fn lookup_address(&self, address: Address) -> Result<String, Self::Error> {
+        CCIPReadMiddleware::lookup_address(self, address).await
+    }
+
+    async fn resolve_avatar(&self, ens_name: &str) -> Result<Url, Self::Error> {
+        CCIPReadMiddleware::resolve_avatar(self, ens_name).await
+    }
+
+    async fn resolve_nft(&self, token: erc::ERCNFT) -> Result<Url, Self::Error> {
+        CCIPReadMiddleware::resolve_nft(self, token).await
+    }
+
+    async fn resolve_field(&self, ens_name: &str, field: &str) -> Result<String, Self::Error> {
+        CCIPReadMiddleware::resolve_field(self, ens_name, field).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::types::{TransactionRequest, H256, U64};
+    use ethers_providers::Provider;
+
+    use crate::{
+        test_utils::{lookup_via, push_responses, revert, success},
+        transport::{GatewayResponse, TransportError},
+    };
+
+    #[tokio::test]
+    async fn test_forwards_to_inner() {
+        let (provider, mock) = Provider::mocked();
+        mock.push::<U64, _>(U64::from(17)).unwrap();
+
+        let middleware = CCIPReadMiddleware::new(provider);
+        let block_number = Middleware::get_block_number(&middleware).await.unwrap();
+
+        assert_eq!(block_number, U64::from(17));
+    }
+
+    #[tokio::test]
+    async fn test_call_goes_through_ccip_read() {
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(Bytes::from(vec![0xca, 0xfe])).unwrap();
+
+        let middleware = CCIPReadMiddleware::new(provider);
+        let tx: TypedTransaction = TransactionRequest::new().to(Address::repeat_byte(1)).into();
+        let result = Middleware::call(&middleware, &tx, None).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![0xca, 0xfe]));
+    }
+
+    #[derive(Debug)]
+    struct CannedTransport;
+
+    #[async_trait]
+    impl GatewayTransport for CannedTransport {
+        async fn request(
+            &self,
+            _sender: Address,
+            _calldata: &[u8],
+            _url: &str,
+        ) -> Result<GatewayResponse, TransportError> {
+            Ok(GatewayResponse {
+                status: 200,
+                body: br#"{"data":"0xcafe"}"#.to_vec().into(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_config_keeps_transport() {
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_transport(CannedTransport)
+            .with_config(CCIPReadConfig::default().max_redirects(2));
+
+        let result = lookup_via(&middleware, &mock, vec!["memory://gateway".to_string()]).await;
+        assert_eq!(result.unwrap(), Bytes::from(vec![1]));
+        assert_eq!(middleware.config().max_redirects, 2);
+    }
+
+    #[test]
+    fn test_try_with_config() {
+        let (provider, _) = Provider::mocked();
+        let config = CCIPReadConfig::default().user_agent("invalid\nagent");
+
+        let error = CCIPReadMiddleware::new(provider).try_with_config(config).unwrap_err();
+        assert!(error.is_builder());
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordedErrors(std::sync::Mutex<Vec<(&'static str, &'static str)>>);
+
+    impl MetricsSink for RecordedErrors {
+        fn error(&self, operation: &'static str, error: &'static str) {
+            self.0.lock().unwrap().push((operation, error));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inherent_methods_count_errors() {
+        let (provider, _) = Provider::mocked();
+        let errors = Arc::new(RecordedErrors::default());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().metrics(errors.clone()));
+
+        // the mock has no responses, so the registry call fails
+        middleware.resolve_name("nick.eth").await.unwrap_err();
+
+        // counted once, by the method called rather than by its calls
+        assert_eq!(*errors.0.lock().unwrap(), [("resolve_name", "ResolverCallError")]);
+    }
+
+    #[tokio::test]
+    async fn test_swallowed_errors_are_not_counted() {
+        let (provider, mock) = Provider::mocked();
+        let errors = Arc::new(RecordedErrors::default());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().metrics(errors.clone()));
+        let resolver = H256::from(Address::repeat_byte(1));
+        let address = Address::repeat_byte(2);
+        // the resolver does not implement ERC-165, its `supportsInterface` reverts
+        push_responses(
+            &mock,
+            vec![success(resolver.as_bytes()), revert(&[]), success(H256::from(address).as_bytes())],
+        );
+
+        assert_eq!(middleware.resolve_name("nick.eth").await.unwrap(), address);
+        assert!(errors.0.lock().unwrap().is_empty());
+    }
 }
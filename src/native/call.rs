@@ -1,149 +1,614 @@
-use std::str::FromStr;
+use std::{borrow::Cow, error::Error, fmt::Display, time::Instant};
 
-use async_recursion::async_recursion;
 use ethers_core::{
-    abi::{self, Address, ParamType, Token},
+    abi::Address,
     types::{transaction::eip2718::TypedTransaction, BlockId, BlockNumber, Bytes, NameOrAddress},
-    utils::serialize,
 };
 use ethers_providers::{Middleware, MiddlewareError};
-use hex::FromHex;
+use tracing::{instrument, Instrument, Span};
 
-use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
+use crate::{
+    error::CCIPMiddlewareError,
+    utils::{offchain_lookup::OffchainLookup, revert_data::extract_revert_data},
+    CCIPReadMiddleware,
+};
+
+/// The `OffchainLookup` reverts followed by a call, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupHops {
+    inner: Vec<OffchainLookup>,
+}
+
+impl LookupHops {
+    /// Every lookup, the last one repeating an earlier one
+    pub fn hops(&self) -> &[OffchainLookup] {
+        &self.inner
+    }
+}
 
-static MAX_CCIP_REDIRECT_ATTEMPT: u8 = 10;
+impl Display for LookupHops {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (hop, lookup) in self.inner.iter().enumerate() {
+            writeln!(f, "hop {}: sender {:?}, callData {}", hop, lookup.sender, lookup.call_data)?;
+        }
+        Ok(())
+    }
+}
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
+    #[instrument(level = "debug", skip_all, fields(to = ?tx.to(), block = ?block))]
     pub async fn call(
         &self,
         tx: &TypedTransaction,
         block: Option<BlockId>,
     ) -> Result<Bytes, CCIPMiddlewareError<M>> {
-        self._call(tx, block, 0).await
+        self.run("call", tx, block, None).await
+    }
+
+    /// Follows the `OffchainLookup` of `revert_data`, obtained by calling `tx`
+    /// some other way than through this middleware, e.g. from a simulation, a
+    /// trace or a failed `eth_estimateGas`
+    ///
+    /// The gateways and callbacks are handled exactly like in [`call`](Self::call),
+    /// hooks, deadline and hop limit included, `revert_data` being the first hop.
+    /// Any other revert fails with a
+    /// [`ContractRevert`](CCIPMiddlewareError::ContractRevert), like the call would.
+    #[instrument(level = "debug", skip_all, fields(to = ?tx.to(), block = ?block))]
+    pub async fn handle_offchain_lookup(
+        &self,
+        tx: &TypedTransaction,
+        revert_data: impl Into<Bytes>,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, CCIPMiddlewareError<M>> {
+        self.run("handle_offchain_lookup", tx, block, Some(revert_data.into()))
+            .await
+    }
+
+    /// Follows the lookups of a call made by the user within the deadline, then
+    /// runs the `on_result` hooks and counts its error
+    async fn run(
+        &self,
+        operation: &'static str,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+        revert_data: Option<Bytes>,
+    ) -> Result<Bytes, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self.follow_lookups(tx, block, revert_data, deadline))
+            .await;
+
+        for hook in &self.config().hooks {
+            let result = result.as_ref().map_err(|error| error as &(dyn Error + Send + Sync));
+            hook.on_result(tx, result).await;
+        }
+        self.observe(operation, result)
     }
 
-    #[async_recursion]
+    /// Calls `transaction`, following every `OffchainLookup` revert until the
+    /// contract returns or `deadline` is reached
+    ///
+    /// Used by the other methods of the middleware, which report the result
+    /// themselves: no `on_result` hook is run and no error counted.
     pub async fn _call(
         &self,
         transaction: &TypedTransaction,
         block_id: Option<BlockId>,
-        attempt: u8,
+        deadline: Option<Instant>,
     ) -> Result<Bytes, CCIPMiddlewareError<M>> {
-        if attempt >= MAX_CCIP_REDIRECT_ATTEMPT {
-            // may need more info
-            return Err(CCIPMiddlewareError::MaxRedirectionError);
-        }
+        self.follow_lookups(transaction, block_id, None, deadline)
+            .await
+    }
 
-        let tx_sender = match transaction.to().unwrap() {
-            NameOrAddress::Name(ens_name) => self.resolve_name(ens_name).await?,
-            NameOrAddress::Address(addr) => *addr,
+    /// The CCIP-Read loop, starting from `revert_data` if set or by calling `transaction`
+    ///
+    /// Each hop is kept so that a contract asking again for a lookup it already
+    /// got, with the same sender and call data, fails right away with a
+    /// [`LookupLoopError`](CCIPMiddlewareError::LookupLoopError) instead of
+    /// bouncing until `max_redirects`.
+    async fn follow_lookups(
+        &self,
+        transaction: &TypedTransaction,
+        mut block_id: Option<BlockId>,
+        mut revert_data: Option<Bytes>,
+        deadline: Option<Instant>,
+    ) -> Result<Bytes, CCIPMiddlewareError<M>> {
+        let tx_sender = match transaction.to() {
+            // boxed, as resolving the name goes through `_call` again
+            Some(NameOrAddress::Name(ens_name)) => {
+                Box::pin(self._resolve_name_at(ens_name, block_id, deadline)).await?
+            }
+            Some(NameOrAddress::Address(addr)) => *addr,
+            // contract creations can't be resolved offchain
+            None => Address::zero(),
         };
 
-        // let tx_value: Value = utils::serialize(transaction);
-        let block_value = serialize(&block_id.unwrap_or_else(|| BlockNumber::Latest.into()));
-        let result = match self.inner().call(transaction, block_id).await {
-            Ok(response) => response.to_string(),
-            Err(provider_error) => {
-                println!("GOT ERROR RESULT {:?}", provider_error);
+        let mut transaction = Cow::Borrowed(transaction);
+        let mut hops: Vec<OffchainLookup> = vec![];
+        // the callback of a hop is called within its span
+        let mut hop_span = Span::none();
 
-                if !provider_error.is_error_response() {
-                    return Err(CCIPMiddlewareError::MiddlewareError(provider_error));
+        loop {
+            // revert data is never mistaken for the result of the call
+            let (result, reverted) = match revert_data.take() {
+                Some(data) => (data, true),
+                None => {
+                    let start = Instant::now();
+                    let result = self
+                        .inner()
+                        .call(&transaction, block_id)
+                        .instrument(hop_span.clone())
+                        .await;
+                    if !hops.is_empty() {
+                        self.metrics().callback(start.elapsed());
+                    }
+                    match result {
+                        Ok(response) => (response, false),
+                        Err(provider_error) => (Self::revert_data(provider_error)?, true),
+                    }
                 }
+            };
 
-                let content = provider_error
-                    .as_error_response().unwrap();
-                let data = content.data.as_ref().unwrap_or(&serde_json::Value::Null);
-                if data.is_null() {
-                    return Err(CCIPMiddlewareError::TodoError(format!("ND {:?}", provider_error)));
-                }
-                data.to_string()
-                    .trim_matches('"')
-                    .trim_start_matches("0x")
-                    .to_string()
+            // EIP-3668: only a revert asks for a lookup, return data is returned as is
+            if !reverted {
+                self.metrics().lookup_hops(hops.len() as u8);
+                return Ok(result);
+            }
+            if tx_sender.is_zero() || !OffchainLookup::matches(&result) {
+                return Err(CCIPMiddlewareError::contract_revert(&result));
             }
-        };
 
-        if block_value.eq("latest")
-            && !tx_sender.is_zero()
-            && result.starts_with("556f1830")
-            && hex::decode(result.clone()).unwrap().len() % 32 == 4
-        {
-            let output_types = vec![
-                ParamType::Address,                            // 'address'
-                ParamType::Array(Box::new(ParamType::String)), // 'string[]'
-                ParamType::Bytes,                              // 'bytes'
-                ParamType::FixedBytes(4),                      // 'bytes4'
-                ParamType::Bytes,                              // 'bytes'
-            ];
-
-            let decoded_data: Vec<abi::Token> =
-                abi::decode(&output_types, &Vec::from_hex(&result.clone()[8..]).unwrap()).unwrap();
-
-            if let (
-                Token::Address(addr),
-                Token::Array(strings),
-                Token::Bytes(bytes),
-                Token::FixedBytes(bytes4),
-                Token::Bytes(bytes2),
-            ) = (
-                decoded_data.get(0).unwrap(),
-                decoded_data.get(1).unwrap(),
-                decoded_data.get(2).unwrap(),
-                decoded_data.get(3).unwrap(),
-                decoded_data.get(4).unwrap(),
-            ) {
-                let sender: Address = *addr;
-                let urls: Vec<&str> = strings
-                    .iter()
-                    .map(|t| match t {
-                        Token::String(s) => s.as_str(),
-                        _ => panic!("CCIP Read contained corrupt URL string"),
-                    })
-                    .collect();
-
-                let call_data: &[u8] = bytes;
-                let callback_selector: Vec<u8> = bytes4.clone();
-                let extra_data: &[u8] = bytes2;
-
-                if !sender.eq(&tx_sender) {
-                    return Err(CCIPMiddlewareError::TodoError("SenderError".to_string()));
-                }
+            if hops.len() >= self.config().max_redirects as usize {
+                // may need more info
+                return Err(CCIPMiddlewareError::MaxRedirectionError);
+            }
 
-                let ccip_result = self
-                    ._ccip_request(sender, transaction, call_data, urls)
-                    .await?;
-                if ccip_result.is_empty() {
-                    return Err(CCIPMiddlewareError::TodoError(
-                        "GatewayNotFoundError".to_string(),
-                    ));
-                }
+            let lookup = OffchainLookup::decode(&result)?;
+
+            if !lookup.sender.eq(&tx_sender) {
+                return Err(CCIPMiddlewareError::SenderError {
+                    expected: tx_sender,
+                    actual: lookup.sender,
+                });
+            }
+
+            if hops
+                .iter()
+                .any(|hop| hop.sender == lookup.sender && hop.call_data == lookup.call_data)
+            {
+                hops.push(lookup);
+                return Err(CCIPMiddlewareError::LookupLoopError(LookupHops { inner: hops }));
+            }
+
+            // EIP-1898: the callbacks run against the block of the first call. It is
+            // only pinned now, so that calls without a lookup cost a single request
+            if hops.is_empty() {
+                block_id = self.pin_block(block_id).await?;
+            }
 
-                let ccip_result_token = Token::Bytes(ccip_result.as_ref().to_vec());
-                let extra_data_token = Token::Bytes(extra_data.into());
+            for hook in &self.config().hooks {
+                hook.on_lookup(&transaction, &lookup).await;
+            }
 
-                let tokens = vec![ccip_result_token, extra_data_token];
+            hop_span = tracing::debug_span!(
+                "offchain_lookup",
+                hop = hops.len(),
+                sender = ?lookup.sender,
+                urls = lookup.urls.len(),
+            );
 
-                let encoded_data = abi::encode(&tokens);
-                let mut new_transaction = transaction.clone();
-                new_transaction.set_data(Bytes::from(
-                    [callback_selector.clone(), encoded_data.clone()].concat(),
-                ));
+            let urls: Vec<&str> = lookup.urls.iter().map(String::as_str).collect();
+            let ccip_result = self
+                ._ccip_request(lookup.sender, &transaction, &lookup.call_data, urls, deadline)
+                .instrument(hop_span.clone())
+                .await?;
+            if ccip_result.is_empty() {
+                return Err(CCIPMiddlewareError::GatewayNotFoundError);
+            }
 
-                return self._call(&new_transaction, block_id, attempt + 1).await;
+            let mut new_transaction = transaction.into_owned();
+            new_transaction.set_data(lookup.callback_calldata(&ccip_result));
+            for hook in &self.config().hooks {
+                hook.before_callback(&new_transaction, &lookup).await;
             }
+
+            transaction = Cow::Owned(new_transaction);
+            hops.push(lookup);
         }
+    }
+
+    /// The revert data of a failed call, or the error itself if the call did not revert
+    fn revert_data(provider_error: M::Error) -> Result<Bytes, CCIPMiddlewareError<M>> {
+        tracing::debug!(error = %provider_error, "call failed");
+
+        let data = match provider_error.as_error_response() {
+            Some(response) => extract_revert_data(response)?,
+            None => None,
+        };
+        data.ok_or(CCIPMiddlewareError::MiddlewareError(provider_error))
+    }
+
+    /// Turns `block` into its block hash, so that later calls see the same
+    /// state even if the chain moves on or reorgs
+    ///
+    /// The latest and pending blocks are left as is: a lookup at the head of
+    /// the chain follows it.
+    pub(crate) async fn pin_block(
+        &self,
+        block: Option<BlockId>,
+    ) -> Result<Option<BlockId>, CCIPMiddlewareError<M>> {
+        let block = match block {
+            None
+            | Some(BlockId::Hash(_))
+            | Some(BlockId::Number(BlockNumber::Latest))
+            | Some(BlockId::Number(BlockNumber::Pending)) => return Ok(block),
+            Some(block) => block,
+        };
+
+        let hash = self
+            .inner()
+            .get_block(block)
+            .await
+            .map_err(CCIPMiddlewareError::MiddlewareError)?
+            .and_then(|found| found.hash)
+            .ok_or(CCIPMiddlewareError::BlockNotFoundError(block))?;
+        Ok(Some(BlockId::Hash(hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::DecodeError,
+        utils::contract_revert::ContractRevert,
+        test_utils::{push_responses, revert, success, CannedResponse, TestGateway},
+        CCIPReadConfig,
+    };
+    use ethers_core::{
+        types::{Block, TransactionRequest, TxHash, H256},
+        utils::serialize,
+    };
+    use ethers_contract::ContractError;
+    use ethers_providers::{JsonRpcError, MockProvider, MockResponse, Provider};
+
+    fn revert_with(data: &[u8]) -> (CCIPReadMiddleware<Provider<MockProvider>>, TypedTransaction) {
+        revert_with_hex(&format!("0x{}", hex::encode(data)))
+    }
+
+    fn revert_with_hex(data: &str) -> (CCIPReadMiddleware<Provider<MockProvider>>, TypedTransaction) {
+        let (provider, mock) = Provider::mocked();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: Some(serde_json::Value::String(data.to_string())),
+        }));
+
+        let tx = TransactionRequest::new().to(Address::repeat_byte(1)).into();
+        (CCIPReadMiddleware::new(provider), tx)
+    }
+
+    #[tokio::test]
+    async fn test_short_offchain_lookup() {
+        let (middleware, tx) = revert_with(&[0x55, 0x6f, 0x18, 0x30, 0x00]);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::DecodeError(DecodeError::ShortData { actual: 5, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_misaligned_offchain_lookup() {
+        let data = [&OffchainLookup::SELECTOR[..], &[0u8; 5 * 32 + 1]].concat();
+        let (middleware, tx) = revert_with(&data);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::DecodeError(DecodeError::MisalignedData(165))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_offchain_lookup() {
+        // offsets pointing far past the end of the data
+        let data = [&OffchainLookup::SELECTOR[..], &[0xffu8; 5 * 32]].concat();
+        let (middleware, tx) = revert_with(&data);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::DecodeError(DecodeError::InvalidAbi(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_max_redirects() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(1),
+            urls: vec!["https://gateway.example/{sender}/{data}.json".to_string()],
+            ..Default::default()
+        };
+        let (middleware, tx) = revert_with(&lookup.encode());
+        let middleware = middleware.with_config(CCIPReadConfig::default().max_redirects(0));
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(error, CCIPMiddlewareError::MaxRedirectionError));
+    }
+
+    #[tokio::test]
+    async fn test_return_data_is_not_a_lookup() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(1),
+            urls: vec!["https://gateway.example/{sender}/{data}.json".to_string()],
+            ..Default::default()
+        };
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        let tx: TypedTransaction = TransactionRequest::new().to(lookup.sender).into();
+
+        // data starting with the selector, returned rather than reverted
+        push_responses(&mock, vec![success(&lookup.encode())]);
+        let result = middleware.call(&tx, None).await.unwrap();
+        assert_eq!(result, lookup.encode());
+
+        push_responses(&mock, vec![success(&OffchainLookup::SELECTOR)]);
+        let result = middleware.call(&tx, None).await.unwrap();
+        assert_eq!(result, Bytes::from(OffchainLookup::SELECTOR.to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_hex_revert() {
+        let (middleware, tx) = revert_with_hex("0x556f18zz");
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::DecodeError(DecodeError::InvalidHex(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_at_block() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec![gateway.url("/lookup")],
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+        let hash = H256::repeat_byte(0xbb);
+        let block = Block::<TxHash> {
+            hash: Some(hash),
+            ..Default::default()
+        };
+        push_responses(
+            &mock,
+            vec![
+                revert(&lookup.encode()),
+                MockResponse::Value(serde_json::to_value(block).unwrap()),
+                success(&[1]),
+            ],
+        );
+
+        let result = middleware.call(&tx, Some(10u64.into())).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        let mut callback = tx.clone();
+        callback.set_data(lookup.callback_calldata(&[0xca, 0xfe]));
+        let at_number = serialize(&BlockId::from(10u64));
+        let at_hash = serialize(&BlockId::from(hash));
+        mock.assert_request("eth_call", [serialize(&tx), at_number.clone()]).unwrap();
+        mock.assert_request("eth_getBlockByNumber", [at_number, false.into()]).unwrap();
+        mock.assert_request("eth_call", [serialize(&callback), at_hash]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_call_at_block_without_lookup() {
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        let tx: TypedTransaction = TransactionRequest::new().to(Address::repeat_byte(1)).into();
+        push_responses(&mock, vec![success(&[1])]);
+
+        let result = middleware.call(&tx, Some(10u64.into())).await.unwrap();
+
+        // the block is only pinned once a lookup is followed
+        assert_eq!(result, Bytes::from(vec![1]));
+        let at_number = serialize(&BlockId::from(10u64));
+        mock.assert_request("eth_call", [serialize(&tx), at_number]).unwrap();
+        assert!(mock.assert_request("eth_getBlockByNumber", ()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_offchain_lookup_at_missing_block() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(1),
+            urls: vec!["https://gateway.example/{sender}/{data}.json".to_string()],
+            ..Default::default()
+        };
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        let tx: TypedTransaction = TransactionRequest::new().to(lookup.sender).into();
+        push_responses(
+            &mock,
+            vec![revert(&lookup.encode()), MockResponse::Value(serde_json::Value::Null)],
+        );
 
-        let result = match Bytes::from_str(&result) {
-            Ok(bytes) => bytes,
-            Err(error) => {
-                println!("error: {:?}", error);
-                return Err(CCIPMiddlewareError::TodoError("GatewayError".to_string()));
+        let block = BlockId::from(BlockNumber::Finalized);
+        let error = middleware.call(&tx, Some(block)).await.unwrap_err();
+        assert!(matches!(error, CCIPMiddlewareError::BlockNotFoundError(b) if b == block));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_loop() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+
+        let sender = Address::repeat_byte(1);
+        let lookup = |call_data: &[u8]| OffchainLookup {
+            sender,
+            urls: vec![gateway.url("/lookup")],
+            call_data: call_data.to_vec().into(),
+            ..Default::default()
+        };
+        // the callbacks bounce between two lookups forever
+        let (first, second) = (lookup(&[1]), lookup(&[2]));
+        push_responses(
+            &mock,
+            (0..100)
+                .map(|hop| revert(&[&first, &second][hop % 2].encode()))
+                .collect(),
+        );
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+
+        match error {
+            CCIPMiddlewareError::LookupLoopError(hops) => {
+                assert_eq!(hops.hops(), &[first.clone(), second, first][..])
             }
+            other => panic!("expected a lookup loop, got {:?}", other),
+        }
+        assert_eq!(gateway.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_handle_offchain_lookup() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec![gateway.url("/lookup")],
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+        push_responses(&mock, vec![success(&[1])]);
+
+        let result = middleware
+            .handle_offchain_lookup(&tx, lookup.encode(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        let mut callback = tx.clone();
+        callback.set_data(lookup.callback_calldata(&[0xca, 0xfe]));
+        let latest = serialize(&BlockId::from(BlockNumber::Latest));
+        mock.assert_request("eth_call", [serialize(&callback), latest]).unwrap();
+        assert!(mock.assert_request("eth_call", ()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handle_offchain_lookup_checks_sender() {
+        let (provider, _) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(2),
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(Address::repeat_byte(1)).into();
+
+        let error = middleware
+            .handle_offchain_lookup(&tx, lookup.encode(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, CCIPMiddlewareError::SenderError { .. }));
+
+        // the caller may hand over any revert of `tx`
+        let reason = "not found ".repeat(10);
+        let data = [
+            &ContractRevert::ERROR_SELECTOR[..],
+            &ethers_core::abi::encode(&[ethers_core::abi::Token::String(reason.clone())]),
+        ]
+        .concat();
+        assert!(data.len() >= 164);
+        let error = middleware
+            .handle_offchain_lookup(&tx, data, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::ContractRevert { revert: ContractRevert::Error(r), .. } if r == reason
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_contract_revert() {
+        let data = [
+            &ContractRevert::ERROR_SELECTOR[..],
+            &ethers_core::abi::encode(&[ethers_core::abi::Token::String("not found".into())]),
+        ]
+        .concat();
+        let (middleware, tx) = revert_with(&data);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::ContractRevert { revert: ContractRevert::Error(reason), .. } if reason == "not found"
+        ));
+
+        // a bare `revert()` is not an empty result
+        let (middleware, tx) = revert_with(&[]);
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::ContractRevert { revert: ContractRevert::Unknown(data), .. } if data.is_empty()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_callback_revert() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec![gateway.url("/lookup")],
+            ..Default::default()
         };
+        let custom_error = [0xde, 0xad, 0xbe, 0xef, 0x01];
+        push_responses(&mock, vec![revert(&lookup.encode()), revert(&custom_error)]);
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        match error {
+            CCIPMiddlewareError::ContractRevert {
+                revert: ContractRevert::Custom { selector, data },
+                ..
+            } => {
+                assert_eq!(selector, [0xde, 0xad, 0xbe, 0xef]);
+                assert_eq!(data, Bytes::from(custom_error.to_vec()));
+            }
+            other => panic!("expected a custom error, got {:?}", other),
+        }
+    }
+
+    ethers::contract::abigen!(Resolver, r#"[error Unauthorized(address account)]"#);
+
+    #[tokio::test]
+    async fn test_contract_revert_decodes_in_ethers_contract() {
+        let account = Address::repeat_byte(2);
+        let data = ethers_core::abi::AbiEncode::encode(Unauthorized { account });
+        let (middleware, tx) = revert_with(&data);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        let error: ContractError<CCIPReadMiddleware<Provider<MockProvider>>> =
+            ContractError::from_middleware_error(error);
 
-        Ok(result)
+        assert_eq!(error.as_revert(), Some(&Bytes::from(data)));
+        assert_eq!(error.decode_revert::<Unauthorized>(), Some(Unauthorized { account }));
     }
 }
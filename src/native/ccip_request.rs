@@ -1,11 +1,27 @@
-use std::fmt::Display;
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    time::{Duration, Instant, SystemTime},
+};
 
 use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, Bytes};
 use ethers_providers::Middleware;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use rand::seq::SliceRandom;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
 use serde::Deserialize;
 use thiserror::Error;
+use tracing::{field::Empty, instrument, Instrument, Span};
 
-use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
+use crate::{
+    config::GatewayStrategy,
+    error::CCIPMiddlewareError,
+    health::{CircuitState, HealthTracker},
+    hooks::RequestAction,
+    policy::PolicyViolation,
+    transport::{GatewayResponse, ResponseTooLarge, TransportError},
+    CCIPReadMiddleware,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct CCIPReturnType {
@@ -13,8 +29,11 @@ pub struct CCIPReturnType {
     data: Option<String>,
 }
 
+/// Length of the response body kept in a [`CCIPRequestError`]
+const BODY_SNIPPET_LEN: usize = 256;
+
 #[derive(Error, Debug)]
-pub enum CCIPRequestError {
+pub enum CCIPRequestErrorKind {
     #[error("Gateway Error: {0}")]
     GatewayError(String),
 
@@ -24,8 +43,168 @@ pub enum CCIPRequestError {
     #[error("Message")]
     Message(String),
 
-    #[error("Failed to decode {0}")]
-    DecodeDataHex(String),
+    #[error("Failed to decode data: {0}")]
+    DecodeDataHex(#[from] DataHexError),
+
+    /// Thrown when a gateway answers with a non-2xx status and no error message
+    #[error("Unexpected HTTP status")]
+    HttpError,
+
+    /// Thrown when the response body is not the expected JSON
+    #[error("Invalid response: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+
+    /// Thrown when the URL is rejected by the gateway policy, before any request
+    #[error("Rejected by gateway policy: {0}")]
+    PolicyViolation(#[from] PolicyViolation),
+
+    /// Thrown when the response body is larger than the configured limit
+    #[error("Response body exceeds {0} bytes")]
+    ResponseTooLarge(usize),
+
+    /// Thrown when the gateway does not respond within the request timeout
+    #[error("No response within {0:?}")]
+    Timeout(Duration),
+
+    /// Thrown when the transport fails, e.g. on connection errors
+    #[error("Transport error: {0}")]
+    Transport(TransportError),
+
+    /// Thrown when a hook skipped the URL
+    #[error("Skipped by hook: {0}")]
+    SkippedByHook(String),
+
+    /// Thrown when a hook aborted the lookup
+    #[error("Aborted by hook: {0}")]
+    AbortedByHook(String),
+
+    /// Thrown when the URL is skipped because its host keeps failing, see [`CircuitBreaker`](crate::health::CircuitBreaker)
+    #[error("Skipped, the gateway host keeps failing")]
+    CircuitOpen,
+
+    /// Thrown when the URL is skipped because its host asked to wait through a
+    /// `Retry-After` header, for the remaining delay
+    #[error("Skipped, the gateway host asked to retry in {0:?}")]
+    Deferred(Duration),
+}
+
+/// Reasons for the `data` of a gateway response to be rejected
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DataHexError {
+    #[error("data is not 0x-prefixed")]
+    MissingPrefix,
+
+    #[error("data has an odd number of hex digits ({0})")]
+    OddLength(usize),
+
+    #[error(transparent)]
+    InvalidHex(#[from] hex::FromHexError),
+}
+
+/// Decodes the `data` of a gateway response, which must be `0x`-prefixed, even-length hex
+fn decode_data_hex(data: &str) -> Result<Bytes, DataHexError> {
+    let digits = data.strip_prefix("0x").ok_or(DataHexError::MissingPrefix)?;
+    if digits.len() % 2 != 0 {
+        return Err(DataHexError::OddLength(digits.len()));
+    }
+    Ok(hex::decode(digits)?.into())
+}
+
+/// A failed request to a single gateway URL
+#[derive(Error, Debug)]
+pub struct CCIPRequestError {
+    pub url: String,
+    /// HTTP status, if a response was received
+    pub status: Option<u16>,
+    /// The beginning of the response body
+    pub body: String,
+    /// Retry attempt of this URL, 0 for the first request
+    pub attempt: u32,
+    /// Delay requested by the gateway through a `Retry-After` header
+    pub retry_after: Option<Duration>,
+    #[source]
+    pub kind: CCIPRequestErrorKind,
+}
+
+impl CCIPRequestError {
+    fn new(url: &str, response: &GatewayResponse, kind: CCIPRequestErrorKind) -> Self {
+        let body = String::from_utf8_lossy(&response.body);
+        Self {
+            url: url.to_string(),
+            status: Some(response.status),
+            body: body.chars().take(BODY_SNIPPET_LEN).collect(),
+            attempt: 0,
+            retry_after: retry_after(&response.headers),
+            kind,
+        }
+    }
+
+    fn without_response(url: &str, kind: CCIPRequestErrorKind) -> Self {
+        Self {
+            url: url.to_string(),
+            status: None,
+            body: String::new(),
+            attempt: 0,
+            retry_after: None,
+            kind,
+        }
+    }
+
+    fn too_large(url: &str, error: ResponseTooLarge) -> Self {
+        Self {
+            status: Some(error.status),
+            ..Self::without_response(url, CCIPRequestErrorKind::ResponseTooLarge(error.limit))
+        }
+    }
+
+    /// Whether retrying the request may succeed: on timeouts, connection errors,
+    /// deferred hosts, 429 and 5xx responses
+    pub fn is_retryable(&self) -> bool {
+        match &self.kind {
+            CCIPRequestErrorKind::Timeout(_) | CCIPRequestErrorKind::Deferred(_) => true,
+            CCIPRequestErrorKind::Transport(error) => is_retryable_transport_error(error),
+            CCIPRequestErrorKind::ResponseTooLarge(_) => false,
+            _ => self.status.is_some_and(|status| status == 429 || status >= 500),
+        }
+    }
+
+    /// Whether the gateway rejected the request itself, which ends the lookup
+    fn ends_lookup(&self) -> bool {
+        self.status.is_some_and(|status| (400..500).contains(&status) && status != 429)
+    }
+}
+
+/// Parses a `Retry-After` header, either a number of seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+fn is_retryable_transport_error(error: &TransportError) -> bool {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(error) => error.is_connect() || error.is_timeout() || error.is_request() || error.is_body(),
+        // an invalid URL template fails the same way every time
+        None => !error.is::<url::ParseError>(),
+    }
+}
+
+impl Display for CCIPRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url)?;
+        if self.attempt > 0 {
+            write!(f, " (retry {})", self.attempt)?;
+        }
+        if let Some(status) = self.status {
+            write!(f, " (status {})", status)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -33,6 +212,13 @@ pub struct CCIPGatewayErrors {
     inner: Vec<CCIPRequestError>,
 }
 
+impl CCIPGatewayErrors {
+    /// The errors of every gateway URL that was tried, in order
+    pub fn errors(&self) -> &[CCIPRequestError] {
+        &self.inner
+    }
+}
+
 impl Display for CCIPGatewayErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut error_string = String::new();
@@ -45,9 +231,18 @@ impl Display for CCIPGatewayErrors {
     }
 }
 
+/// Every failed attempt at a single gateway URL
+struct URLFailure {
+    /// Position of the URL in the `OffchainLookup`
+    index: usize,
+    errors: Vec<CCIPRequestError>,
+    /// Whether the lookup must end, on a 4xx status other than 429 or when aborted by a hook
+    ends_lookup: bool,
+}
+
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     /// This function makes a Cross-Chain Interoperability Protocol (CCIP-Read) request
     /// and returns the result as `Bytes` or an error message.
@@ -58,68 +253,1092 @@ where
     /// * `tx`: The typed transaction.
     /// * `calldata`: The function call data as bytes.
     /// * `urls`: A vector of Offchain Gateway URLs to send the request to.
+    /// * `deadline`: When the whole call must be done by, no retry is started past it.
     ///
     /// # Returns
     ///
     /// an opaque byte string to send to callbackFunction on Offchain Resolver contract.
+    #[instrument(level = "debug", skip_all, fields(sender = ?sender, urls = urls.len()))]
     pub async fn _ccip_request(
         &self,
         sender: Address,
         tx: &TypedTransaction,
         calldata: &[u8],
         urls: Vec<&str>,
+        deadline: Option<Instant>,
     ) -> Result<Bytes, CCIPMiddlewareError<M>> {
         // If there are no URLs or the transaction's destination is empty, return an empty result
         if urls.is_empty() || tx.to().is_none() {
             return Ok(Bytes::from([]));
         }
 
-        // Convert calldata to a hex string
-        let data: String = calldata
-            .iter()
-            .map(|byte| format!("{:02x}", byte))
-            .collect();
+        self.config()
+            .gateway_policy
+            .check_url_count(urls.len())
+            .map_err(CCIPMiddlewareError::GatewayPolicyError)?;
+
+        let mut urls: Vec<(usize, &str)> = urls.into_iter().enumerate().collect();
+        if self.config().strategy == GatewayStrategy::Shuffled {
+            urls.shuffle(&mut rand::thread_rng());
+        }
+        // hosts that keep failing are tried last
+        urls.sort_by_key(|&(_, url)| {
+            self.health().state(&HealthTracker::host(url)) == CircuitState::Open
+        });
+
+        let result = match self.config().strategy {
+            GatewayStrategy::Sequential | GatewayStrategy::Shuffled => {
+                self.fetch_sequential(sender, calldata, &urls, deadline).await
+            }
+            GatewayStrategy::Parallel => {
+                self.fetch_hedged(sender, calldata, &urls, deadline, Duration::ZERO).await
+            }
+            GatewayStrategy::Hedged(delay) => {
+                self.fetch_hedged(sender, calldata, &urls, deadline, delay).await
+            }
+        };
+
+        // report the errors in the order of the URLs, whatever order they were fetched in
+        let mut failures = match result {
+            Ok(data) => return Ok(data),
+            Err(failures) => failures,
+        };
+        failures.sort_by_key(|failure| failure.index);
+        let inner = failures.into_iter().flat_map(|failure| failure.errors).collect();
+
+        Err(CCIPMiddlewareError::GatewayError(CCIPGatewayErrors { inner }))
+    }
+
+    /// Tries `urls` one after another
+    async fn fetch_sequential(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        urls: &[(usize, &str)],
+        deadline: Option<Instant>,
+    ) -> Result<Bytes, Vec<URLFailure>> {
+        let mut failures = vec![];
+
+        for &(index, url) in urls {
+            let failure = match self.fetch_url(sender, calldata, index, url, deadline).await {
+                Ok(data) => return Ok(data),
+                Err(failure) => failure,
+            };
+            let ends_lookup = failure.ends_lookup;
+            failures.push(failure);
+            if ends_lookup {
+                break;
+            }
+        }
+
+        Err(failures)
+    }
 
-        let mut error_messages = CCIPGatewayErrors { inner: vec![] };
-
-        for (_i, url) in urls.iter().enumerate() {
-            // Replace the placeholders in the URL with the sender address and data
-            let href = url
-                .replace("{sender}", &format!("0x{:x}", sender))
-                .replace("{data}", &format!("0x{}", &data.to_lowercase()).to_string());
-
-            let result: CCIPReturnType = match url.contains("{data}") {
-                true => reqwest::Client::new().get(&href),
-                // If the URL does not contain the "{data}" placeholder, create a POST request instead
-                false => reqwest::Client::new()
-                    .post(&href)
-                    .json(&serde_json::json!({ "data": data, "sender": sender })),
-            }
-            .send()
-            .await?
-            .json()
-            .await?;
-
-            println!("CCIP Request Result: {:?}", result);
-
-            // If the result contains the "data" field, decode the data and return it as Bytes
-            if let Some(returned_data) = result.data {
-                match hex::decode(&returned_data[2..]) {
-                    Ok(decoded) => return Ok(Bytes::from(decoded)),
-                    Err(e) => {
-                        error_messages.inner.push(CCIPRequestError::DecodeDataHex(e.to_string()));
-                        
-                        continue;
-                    },
+    /// Tries `urls` concurrently, starting the next one whenever the running ones
+    /// have not answered within `delay` or have failed. The first response wins
+    /// and the requests still running are cancelled.
+    async fn fetch_hedged(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        urls: &[(usize, &str)],
+        deadline: Option<Instant>,
+        delay: Duration,
+    ) -> Result<Bytes, Vec<URLFailure>> {
+        let mut pending = urls.iter();
+        let mut running = FuturesUnordered::new();
+        let mut failures = vec![];
+        while let Some(&(index, url)) = pending.next() {
+            running.push(self.fetch_url(sender, calldata, index, url, deadline));
+
+            loop {
+                let next = if pending.len() == 0 {
+                    running.next().await
+                } else {
+                    match tokio::time::timeout(delay, running.next()).await {
+                        Ok(next) => next,
+                        // hedge with the next URL
+                        Err(_) => break,
+                    }
+                };
+
+                match next {
+                    Some(Ok(data)) => return Ok(data),
+                    Some(Err(failure)) => {
+                        let ends_lookup = failure.ends_lookup;
+                        failures.push(failure);
+                        if ends_lookup {
+                            return Err(failures);
+                        }
+                        // replace the failed URL right away
+                        if running.is_empty() || pending.len() > 0 {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        Err(failures)
+    }
+
+    /// Every attempt at the gateway `url`, retried according to the retry policy
+    async fn fetch_url(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        index: usize,
+        url: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Bytes, URLFailure> {
+        let mut failure = URLFailure {
+            index,
+            errors: vec![],
+            ends_lookup: false,
+        };
+
+        let mut url = Cow::Borrowed(url);
+        for hook in &self.config().hooks {
+            let kind = match hook.before_request(sender, &url).await {
+                RequestAction::Continue => continue,
+                RequestAction::Rewrite(rewritten) => {
+                    url = Cow::Owned(rewritten);
+                    continue;
+                }
+                RequestAction::Skip(reason) => CCIPRequestErrorKind::SkippedByHook(reason),
+                RequestAction::Abort(reason) => {
+                    failure.ends_lookup = true;
+                    CCIPRequestErrorKind::AbortedByHook(reason)
+                }
+            };
+            failure.errors.push(CCIPRequestError::without_response(&url, kind));
+            return Err(failure);
+        }
+        let url = url.as_ref();
+
+        if let Err(violation) = self.config().gateway_policy.check(url).await {
+            failure.errors.push(CCIPRequestError::without_response(url, violation.into()));
+            return Err(failure);
+        }
+
+        let host = HealthTracker::host(url);
+        if self.health().skip(&host) {
+            let kind = CCIPRequestErrorKind::CircuitOpen;
+            failure.errors.push(CCIPRequestError::without_response(url, kind));
+            return Err(failure);
+        }
+
+        let retry = &self.config().retry;
+        for attempt in 0..=retry.max_retries {
+            if let Err(remaining) = self.rate_limiter().acquire(&host).await {
+                let kind = CCIPRequestErrorKind::Deferred(remaining);
+                failure.errors.push(CCIPRequestError::without_response(url, kind));
+                break;
+            }
+            let span = tracing::debug_span!(
+                "gateway_request",
+                url,
+                attempt,
+                status = Empty,
+                latency_ms = Empty,
+            );
+            let start = Instant::now();
+            let result = self
+                .gateway_request(sender, calldata, url)
+                .instrument(span.clone())
+                .await;
+            span.record("latency_ms", start.elapsed().as_millis() as u64);
+            let status = match &result {
+                Ok((status, _)) => Some(*status),
+                Err(error) => error.status,
+            };
+            self.metrics().gateway_request(&host, status, start.elapsed());
+            let result = result.map(|(_, data)| data);
+            if let Err(error) = &result {
+                span.in_scope(|| tracing::debug!(%error, "gateway request failed"));
+            }
+            for hook in &self.config().hooks {
+                hook.after_response(url, result.as_ref()).await;
+            }
+            let mut error = match result {
+                Ok(data) => {
+                    self.health().record_success(&host, start.elapsed());
+                    return Ok(data);
+                }
+                Err(error) => error,
+            };
+            error.attempt = attempt;
+
+            // EIP-3668: 4xx ends the lookup, anything else tries the next URL
+            failure.ends_lookup = error.ends_lookup();
+            // a 4xx is an invalid request rather than an unhealthy gateway
+            if !failure.ends_lookup {
+                let latency = error.status.map(|_| start.elapsed());
+                self.health().record_failure(&host, latency);
+            }
+            let retryable = !failure.ends_lookup && error.is_retryable();
+            let retry_after = error.retry_after;
+            failure.errors.push(error);
+            if let Some(retry_after) = retry_after {
+                self.rate_limiter().defer(&host, retry_after);
+            }
+            if !retryable || attempt == retry.max_retries {
+                break;
+            }
+
+            // a gateway asking for more than a backoff is left for the next URL
+            let retry_after = retry_after.unwrap_or_default();
+            if retry_after > retry.max_backoff {
+                break;
+            }
+            let backoff = retry.backoff(attempt).max(retry_after);
+            if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+                break;
+            }
+            tokio::time::sleep(backoff).await;
+        }
+
+        Err(failure)
+    }
+
+    /// A single attempt at fetching the response of the gateway `url`, with its status
+    async fn gateway_request(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        url: &str,
+    ) -> Result<(u16, Bytes), CCIPRequestError> {
+        let request = self.transport().request(sender, calldata, url);
+        let response = match self.config().request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request).await.map_err(|_| {
+                CCIPRequestError::without_response(url, CCIPRequestErrorKind::Timeout(timeout))
+            })?,
+            None => request.await,
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => match error.downcast::<ResponseTooLarge>() {
+                Ok(error) => return Err(CCIPRequestError::too_large(url, *error)),
+                Err(error) => {
+                    return Err(CCIPRequestError::without_response(
+                        url,
+                        CCIPRequestErrorKind::Transport(error),
+                    ))
                 }
+            },
+        };
+
+        // custom transports may not enforce the limit themselves
+        let limit = self.config().max_response_size;
+        if response.body.len() > limit {
+            let status = response.status;
+            return Err(CCIPRequestError::too_large(url, ResponseTooLarge { status, limit }));
+        }
+
+        let status = response.status;
+        Span::current().record("status", status);
+        let result = serde_json::from_slice::<CCIPReturnType>(&response.body);
+        tracing::trace!(?result, "gateway response");
+
+        if !(200..300).contains(&status) {
+            let kind = match result.ok().and_then(|result| result.message) {
+                Some(message) => CCIPRequestErrorKind::GatewayError(message),
+                None => CCIPRequestErrorKind::HttpError,
             };
+            return Err(CCIPRequestError::new(url, &response, kind));
+        }
+
+        let result = result.map_err(|e| CCIPRequestError::new(url, &response, e.into()))?;
+
+        // If the result contains the "data" field, decode the data and return it as Bytes
+        if let Some(returned_data) = result.data {
+            return decode_data_hex(&returned_data)
+                .map(|data| (status, data))
+                .map_err(|e| CCIPRequestError::new(url, &response, e.into()));
+        };
+
+        let kind = match result.message {
+            Some(message) => CCIPRequestErrorKind::GatewayError(message),
+            None => CCIPRequestErrorKind::NoMessage(),
+        };
+        Err(CCIPRequestError::new(url, &response, kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use async_trait::async_trait;
+    use ethers_core::types::TransactionRequest;
+    use ethers_providers::{MockProvider, Provider};
+    use reqwest::header::HeaderValue;
+
+    use crate::{
+        health::CircuitBreaker,
+        host_options::HostOptions,
+        policy::GatewayPolicy,
+        rate_limit::RateLimit,
+        retry::RetryPolicy,
+        test_utils::{
+            lookup_via, push_responses, revert, success, CannedResponse, SpanRecorder, TestGateway,
+        },
+        transport::{GatewayResponse, GatewayTransport, TransportError},
+        utils::offchain_lookup::OffchainLookup,
+        CCIPReadConfig, CCIPReadMiddleware, GatewayStrategy,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_gateway_connections_are_reused() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec![gateway.url("/lookup")],
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+
+        for _ in 0..3 {
+            push_responses(&mock, vec![revert(&lookup.encode()), success(&[1])]);
+            let result = middleware.call(&tx, None).await.unwrap();
+            assert_eq!(result, Bytes::from(vec![1]));
+        }
+
+        // a cloned middleware shares the same connection pool
+        let cloned = middleware.clone();
+        push_responses(&mock, vec![revert(&lookup.encode()), success(&[1])]);
+        cloned.call(&tx, None).await.unwrap();
+
+        assert_eq!(gateway.requests().len(), 4);
+        assert_eq!(gateway.connections(), 1);
+    }
+
+    async fn lookup_through(
+        gateway: &TestGateway,
+        paths: &[&str],
+    ) -> Result<Bytes, CCIPMiddlewareError<Provider<MockProvider>>> {
+        lookup_with(CCIPReadConfig::default(), gateway, paths).await
+    }
+
+    async fn lookup_with(
+        config: CCIPReadConfig,
+        gateway: &TestGateway,
+        paths: &[&str],
+    ) -> Result<Bytes, CCIPMiddlewareError<Provider<MockProvider>>> {
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider).with_config(config);
+        let urls = paths.iter().map(|path| gateway.url(path)).collect();
+        lookup_via(&middleware, &mock, urls).await
+    }
+
+    fn gateway_errors<M: Middleware + 'static>(error: CCIPMiddlewareError<M>) -> CCIPGatewayErrors {
+        match error {
+            CCIPMiddlewareError::GatewayError(errors) => errors,
+            other => panic!("expected gateway errors, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tracing_spans() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        lookup_through(&gateway, &["/lookup"]).await.unwrap();
 
-            error_messages.inner.push(match result.message {
-                Some(message) => CCIPRequestError::GatewayError(message),
-                None => CCIPRequestError::NoMessage(),
+        assert_eq!(recorder.spans("call").len(), 1);
+        assert_eq!(recorder.field("offchain_lookup", "hop"), Some("0".to_string()));
+        assert_eq!(
+            recorder.field("gateway_request", "url"),
+            Some(gateway.url("/lookup"))
+        );
+        assert_eq!(recorder.field("gateway_request", "status"), Some("200".to_string()));
+        assert!(recorder.field("gateway_request", "latency_ms").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_server_error_tries_next_url() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/down" => CannedResponse::new(502, "<html>Bad Gateway</html>"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        let result = lookup_through(&gateway, &["/down", "/up"]).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        assert_eq!(gateway.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ends_lookup_stops_lookup() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/missing" => CannedResponse::new(404, r#"{"message":"not found"}"#),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        let error = lookup_through(&gateway, &["/missing", "/up"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+
+        assert_eq!(gateway.requests().len(), 1);
+        assert_eq!(errors.errors().len(), 1);
+        assert_eq!(errors.errors()[0].status, Some(404));
+        assert_eq!(errors.errors()[0].url, gateway.url("/missing"));
+        assert!(matches!(
+            &errors.errors()[0].kind,
+            CCIPRequestErrorKind::GatewayError(message) if message == "not found"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_errors_are_recorded() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/html" => CannedResponse::new(200, "<html>maintenance</html>"),
+            _ => CannedResponse::new(503, "unavailable"),
+        })
+        .await;
+
+        let error = lookup_through(&gateway, &["/html", "/busy"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+
+        assert_eq!(errors.errors().len(), 2);
+        assert_eq!(errors.errors()[0].status, Some(200));
+        assert_eq!(errors.errors()[0].body, "<html>maintenance</html>");
+        assert!(matches!(
+            errors.errors()[0].kind,
+            CCIPRequestErrorKind::InvalidResponse(_)
+        ));
+        assert_eq!(errors.errors()[1].status, Some(503));
+        assert_eq!(errors.errors()[1].url, gateway.url("/busy"));
+        assert!(matches!(errors.errors()[1].kind, CCIPRequestErrorKind::HttpError));
+    }
+
+    #[test]
+    fn test_decode_data_hex() {
+        assert_eq!(decode_data_hex("0xcafe").unwrap(), Bytes::from(vec![0xca, 0xfe]));
+        assert_eq!(decode_data_hex("0x").unwrap(), Bytes::default());
+        assert_eq!(decode_data_hex(""), Err(DataHexError::MissingPrefix));
+        assert_eq!(decode_data_hex("x"), Err(DataHexError::MissingPrefix));
+        assert_eq!(decode_data_hex("cafe"), Err(DataHexError::MissingPrefix));
+        assert_eq!(decode_data_hex("0xcaf"), Err(DataHexError::OddLength(3)));
+        assert!(matches!(
+            decode_data_hex("0xzz"),
+            Err(DataHexError::InvalidHex(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_data_tries_next_url() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/short" => CannedResponse::new(200, r#"{"data":"x"}"#),
+            "/odd" => CannedResponse::new(200, r#"{"data":"0xabc"}"#),
+            _ => CannedResponse::new(200, r#"{"data":"cafe"}"#),
+        })
+        .await;
+
+        let error = lookup_through(&gateway, &["/short", "/odd", "/unprefixed"]).await.unwrap_err();
+        let kinds: Vec<_> = gateway_errors(error)
+            .inner
+            .into_iter()
+            .map(|error| error.kind)
+            .collect();
+
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                CCIPRequestErrorKind::DecodeDataHex(DataHexError::MissingPrefix),
+                CCIPRequestErrorKind::DecodeDataHex(DataHexError::OddLength(3)),
+                CCIPRequestErrorKind::DecodeDataHex(DataHexError::MissingPrefix),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_response_size_limit() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/huge" => CannedResponse::data(&[0xff; 4096]),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+        let config = CCIPReadConfig::default().max_response_size(1024);
+
+        let result = lookup_with(config.clone(), &gateway, &["/huge", "/small"]).await;
+        assert_eq!(result.unwrap(), Bytes::from(vec![1]));
+
+        let error = lookup_with(config, &gateway, &["/huge"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+        assert_eq!(errors.errors()[0].status, Some(200));
+        assert!(matches!(
+            errors.errors()[0].kind,
+            CCIPRequestErrorKind::ResponseTooLarge(1024)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_policy_is_enforced_before_requests() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let config = CCIPReadConfig::default().gateway_policy(GatewayPolicy::strict());
+
+        let error = lookup_with(config, &gateway, &["/a", "/b"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+
+        assert!(gateway.requests().is_empty());
+        assert_eq!(errors.errors().len(), 2);
+        assert_eq!(errors.errors()[0].status, None);
+        assert!(matches!(
+            errors.errors()[0].kind,
+            CCIPRequestErrorKind::PolicyViolation(PolicyViolation::Scheme(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_policy_url_limit() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let config =
+            CCIPReadConfig::default().gateway_policy(GatewayPolicy::default().max_urls(1));
+
+        let error = lookup_with(config, &gateway, &["/a", "/b"]).await.unwrap_err();
+
+        assert!(gateway.requests().is_empty());
+        assert!(matches!(
+            error,
+            CCIPMiddlewareError::GatewayPolicyError(PolicyViolation::TooManyURLs { count: 2, max: 1 })
+        ));
+    }
+
+    #[derive(Debug, Default)]
+    struct InMemoryGateway {
+        seen: Mutex<Vec<(Address, Vec<u8>)>>,
+    }
+
+    #[async_trait]
+    impl GatewayTransport for InMemoryGateway {
+        async fn request(
+            &self,
+            sender: Address,
+            calldata: &[u8],
+            url: &str,
+        ) -> Result<GatewayResponse, TransportError> {
+            if url != "memory://gateway" {
+                return Err(format!("unknown gateway {}", url).into());
+            }
+            self.seen.lock().unwrap().push((sender, calldata.to_vec()));
+            Ok(GatewayResponse {
+                status: 200,
+                body: br#"{"data":"0xcafe"}"#.to_vec().into(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_transport() {
+        let (provider, mock) = Provider::mocked();
+        let transport = Arc::new(InMemoryGateway::default());
+        let middleware = CCIPReadMiddleware::new(provider).with_transport(transport.clone());
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec!["memory://gateway".to_string()],
+            call_data: Bytes::from(vec![0xab]),
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+        push_responses(&mock, vec![revert(&lookup.encode()), success(&[1])]);
+
+        assert_eq!(middleware.call(&tx, None).await.unwrap(), Bytes::from(vec![1]));
+        assert_eq!(*transport.seen.lock().unwrap(), vec![(sender, vec![0xab])]);
+    }
+
+    #[tokio::test]
+    async fn test_custom_transport_error() {
+        let (provider, mock) = Provider::mocked();
+        let middleware =
+            CCIPReadMiddleware::new(provider).with_transport(InMemoryGateway::default());
+
+        let sender = Address::repeat_byte(1);
+        let lookup = OffchainLookup {
+            sender,
+            urls: vec!["memory://elsewhere".to_string()],
+            ..Default::default()
+        };
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+        push_responses(&mock, vec![revert(&lookup.encode())]);
+
+        let error = middleware.call(&tx, None).await.unwrap_err();
+        let errors = gateway_errors(error);
+        assert_eq!(errors.errors().len(), 1);
+        assert!(matches!(errors.errors()[0].kind, CCIPRequestErrorKind::Transport(_)));
+    }
+
+    /// Answers `memory://<millis>/<status>` with `status` after `millis`, the data being `millis`
+    #[derive(Debug, Default)]
+    struct DelayedGateway {
+        started: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl GatewayTransport for DelayedGateway {
+        async fn request(
+            &self,
+            _sender: Address,
+            _calldata: &[u8],
+            url: &str,
+        ) -> Result<GatewayResponse, TransportError> {
+            self.started.lock().unwrap().push(url.to_string());
+            let (millis, status) = url
+                .trim_start_matches("memory://")
+                .split_once('/')
+                .ok_or("invalid url")?;
+            let millis: u16 = millis.parse()?;
+            tokio::time::sleep(Duration::from_millis(millis.into())).await;
+
+            Ok(GatewayResponse {
+                status: status.parse()?,
+                body: format!(r#"{{"data":"0x{:04x}"}}"#, millis).into_bytes().into(),
+                ..Default::default()
+            })
+        }
+    }
+
+    async fn fetch_with(
+        strategy: GatewayStrategy,
+        urls: &[&str],
+    ) -> (Result<Bytes, CCIPMiddlewareError<Provider<MockProvider>>>, Duration, Vec<String>) {
+        let (provider, _) = Provider::mocked();
+        let transport = Arc::new(DelayedGateway::default());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().strategy(strategy))
+            .with_transport(transport.clone());
+        let sender = Address::repeat_byte(1);
+        let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+
+        let start = Instant::now();
+        let result = middleware._ccip_request(sender, &tx, &[], urls.to_vec(), None).await;
+        let started = transport.started.lock().unwrap().clone();
+        (result, start.elapsed(), started)
+    }
+
+    #[tokio::test]
+    async fn test_sequential_strategy() {
+        let (result, elapsed, started) =
+            fetch_with(GatewayStrategy::Sequential, &["memory://200/200", "memory://10/200"]).await;
+
+        assert_eq!(result.unwrap(), Bytes::from(200u16.to_be_bytes().to_vec()));
+        assert!(elapsed >= Duration::from_millis(200));
+        assert_eq!(started, vec!["memory://200/200"]);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_strategy() {
+        let (result, elapsed, started) =
+            fetch_with(GatewayStrategy::Parallel, &["memory://500/200", "memory://10/200"]).await;
+
+        assert_eq!(result.unwrap(), Bytes::from(10u16.to_be_bytes().to_vec()));
+        assert!(elapsed < Duration::from_millis(500));
+        assert_eq!(started.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_strategy() {
+        let delay = Duration::from_millis(50);
+        let urls = ["memory://500/200", "memory://10/200", "memory://10/200"];
+        let (result, elapsed, started) = fetch_with(GatewayStrategy::Hedged(delay), &urls).await;
+
+        assert_eq!(result.unwrap(), Bytes::from(10u16.to_be_bytes().to_vec()));
+        assert!(elapsed >= delay && elapsed < Duration::from_millis(500));
+        assert_eq!(started, vec!["memory://500/200", "memory://10/200"]);
+
+        // a failed URL is replaced without waiting for the delay
+        let delay = Duration::from_secs(10);
+        let urls = ["memory://0/503", "memory://10/200"];
+        let (result, elapsed, _) = fetch_with(GatewayStrategy::Hedged(delay), &urls).await;
+
+        assert_eq!(result.unwrap(), Bytes::from(10u16.to_be_bytes().to_vec()));
+        assert!(elapsed < delay);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_errors_keep_url_order() {
+        let urls = ["memory://100/503", "memory://0/502", "memory://50/500"];
+        for strategy in [GatewayStrategy::Parallel, GatewayStrategy::Shuffled] {
+            let (result, _, started) = fetch_with(strategy, &urls).await;
+            let errors = gateway_errors(result.unwrap_err());
+
+            assert_eq!(started.len(), 3);
+            let statuses: Vec<_> = errors.errors().iter().map(|error| error.status).collect();
+            assert_eq!(statuses, vec![Some(503), Some(502), Some(500)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_hosts_are_tried_last() {
+        let down = TestGateway::start(|_| CannedResponse::new(503, "unavailable")).await;
+        let up = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let breaker = CircuitBreaker::default().failure_threshold(1);
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().circuit_breaker(breaker));
+
+        for _ in 0..3 {
+            let urls = vec![down.url("/"), up.url("/")];
+            assert!(lookup_via(&middleware, &mock, urls).await.is_ok());
+        }
+
+        assert_eq!(down.requests().len(), 1);
+        assert_eq!(up.requests().len(), 3);
+
+        let health = middleware.gateway_health();
+        let down_health = health.iter().find(|h| h.host == down.addr.to_string()).unwrap();
+        let up_health = health.iter().find(|h| h.host == up.addr.to_string()).unwrap();
+        assert_eq!((down_health.failures, down_health.state), (1, CircuitState::Open));
+        assert_eq!((up_health.successes, up_health.state), (3, CircuitState::Closed));
+        assert!(up_health.average_latency.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_failing_hosts_are_skipped() {
+        let down = TestGateway::start(|_| CannedResponse::new(503, "unavailable")).await;
+        let (provider, mock) = Provider::mocked();
+        let breaker = CircuitBreaker::default().failure_threshold(1).skip_open_hosts(true);
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().circuit_breaker(breaker));
+
+        lookup_via(&middleware, &mock, vec![down.url("/")]).await.unwrap_err();
+        let error = lookup_via(&middleware, &mock, vec![down.url("/")]).await.unwrap_err();
+
+        assert_eq!(down.requests().len(), 1);
+        assert!(matches!(
+            gateway_errors(error).errors()[0].kind,
+            CCIPRequestErrorKind::CircuitOpen
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_host_options() {
+        let partner = TestGateway::start(|_| CannedResponse::new(503, "unavailable")).await;
+        let other = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let options = HostOptions::new("127.0.0.1")
+            .allow_http(true)
+            .bearer_auth("secret")
+            .header_fn(|url, headers| {
+                headers.insert("x-gateway-path", HeaderValue::from_str(url.path()).unwrap());
             });
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().user_agent("test-agent").host(options));
+
+        let urls = vec![
+            partner.url("/partner"),
+            format!("http://localhost:{}/other", other.addr.port()),
+        ];
+        lookup_via(&middleware, &mock, urls).await.unwrap();
+
+        let partner_request = &partner.requests()[0];
+        assert_eq!(partner_request.header("authorization"), Some("Bearer secret"));
+        assert_eq!(partner_request.header("x-gateway-path"), Some("/partner"));
+        assert_eq!(partner_request.header("user-agent"), Some("test-agent"));
+
+        let other_request = &other.requests()[0];
+        assert_eq!(other_request.header("authorization"), None);
+        assert_eq!(other_request.header("x-gateway-path"), None);
+        assert_eq!(other_request.header("user-agent"), Some("test-agent"));
+    }
+
+    #[tokio::test]
+    async fn test_host_options_redirects_stay_on_host() {
+        let other = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let location = format!("http://localhost:{}/stolen", other.addr.port());
+        let partner =
+            TestGateway::start(move |_| CannedResponse::new(302, "").header("Location", &location))
+                .await;
+        let (provider, mock) = Provider::mocked();
+        let options = HostOptions::new("127.0.0.1").allow_http(true).bearer_auth("secret");
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().host(options));
+
+        let error = lookup_via(&middleware, &mock, vec![partner.url("/")]).await.unwrap_err();
+
+        assert!(other.requests().is_empty());
+        assert!(matches!(
+            gateway_errors(error).errors()[0].kind,
+            CCIPRequestErrorKind::Transport(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_host_options_need_https() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let options = HostOptions::new("127.0.0.1").bearer_auth("secret");
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().host(options));
+
+        lookup_via(&middleware, &mock, vec![gateway.url("/")]).await.unwrap();
+
+        assert_eq!(gateway.requests()[0].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn test_private_hosts_are_not_connected_to() {
+        let private = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let location = format!("http://localhost:{}/", private.addr.port());
+        let gateway =
+            TestGateway::start(move |_| CannedResponse::new(302, "").header("Location", &location))
+                .await;
+        let (provider, _) = Provider::mocked();
+        let policy = GatewayPolicy::default().block_private_ips(true);
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().gateway_policy(policy));
+
+        // the policy rejects the loopback gateway before any request, going
+        // straight to the transport stands in for a public gateway
+        let transport = middleware.transport();
+        let redirected = transport.request(Address::zero(), &[], &gateway.url("/")).await;
+        let location = format!("http://localhost:{}/", private.addr.port());
+        let direct = transport.request(Address::zero(), &[], &location).await;
+
+        assert!(redirected.is_err());
+        assert!(direct.is_err());
+        assert_eq!(gateway.requests().len(), 1);
+        assert!(private.requests().is_empty());
+    }
+
+    fn retrying(max_retries: u32) -> CCIPReadConfig {
+        CCIPReadConfig::default()
+            .retry(RetryPolicy::new(max_retries).initial_backoff(Duration::from_millis(1)))
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        headers.insert(RETRY_AFTER, date.parse().unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(58) && delay <= Duration::from_secs(60));
+
+        headers.insert(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_requests() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/limited" => CannedResponse::new(429, "slow down").header("Retry-After", "30"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        // unlike other 4xx, a 429 does not end the lookup
+        let result = lookup_through(&gateway, &["/limited", "/up"]).await.unwrap();
+        assert_eq!(result, Bytes::from(vec![1]));
+
+        let error = lookup_through(&gateway, &["/limited"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+        assert_eq!(errors.errors()[0].status, Some(429));
+        assert_eq!(errors.errors()[0].retry_after, Some(Duration::from_secs(30)));
+        assert!(errors.errors()[0].is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_honoured() {
+        let calls = AtomicUsize::new(0);
+        let gateway = TestGateway::start(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+            0 => CannedResponse::new(429, "slow down").header("Retry-After", "1"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        let start = Instant::now();
+        let result = lookup_with(retrying(1), &gateway, &["/limited"]).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(gateway.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_moves_on() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/limited" => CannedResponse::new(429, "slow down").header("Retry-After", "30"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        let start = Instant::now();
+        let result = lookup_with(retrying(3), &gateway, &["/limited", "/up"]).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let paths: Vec<_> = gateway.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(paths, ["/limited", "/up"]);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_host_is_skipped() {
+        let gateway = TestGateway::start(|_| {
+            CannedResponse::new(429, "slow down").header("Retry-After", "30")
+        })
+        .await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().rate_limit(RateLimit::new(20.0)));
+
+        lookup_via(&middleware, &mock, vec![gateway.url("/")]).await.unwrap_err();
+        let start = Instant::now();
+        let error = lookup_via(&middleware, &mock, vec![gateway.url("/")]).await.unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(gateway.requests().len(), 1);
+        let errors = gateway_errors(error);
+        assert!(matches!(errors.errors()[0].kind, CCIPRequestErrorKind::Deferred(_)));
+        assert!(errors.errors()[0].is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_huge_retry_after() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/limited" => {
+                CannedResponse::new(429, "slow down").header("Retry-After", &u64::MAX.to_string())
+            }
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider).with_config(
+            CCIPReadConfig::default()
+                .rate_limit(RateLimit::new(20.0))
+                .retry(RetryPolicy::new(3)),
+        );
+
+        // the host is deferred for the rest of the lookup rather than panicking
+        let urls = vec![gateway.url("/limited"), gateway.url("/up")];
+        let error = lookup_via(&middleware, &mock, urls).await.unwrap_err();
+
+        assert_eq!(gateway.requests().len(), 1);
+        let errors = gateway_errors(error);
+        match errors.errors()[1].kind {
+            CCIPRequestErrorKind::Deferred(remaining) => {
+                assert!(remaining <= Duration::from_secs(60 * 60))
+            }
+            ref kind => panic!("expected the host to be deferred, got {:?}", kind),
         }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().rate_limit(RateLimit::new(20.0)));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            lookup_via(&middleware, &mock, vec![gateway.url("/")]).await.unwrap();
+        }
+
+        // the first request uses the burst, the next two wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(95));
+        assert_eq!(gateway.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried() {
+        let calls = AtomicUsize::new(0);
+        let gateway = TestGateway::start(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => CannedResponse::new(503, "unavailable"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+
+        let result = lookup_with(retrying(2), &gateway, &["/flaky"]).await.unwrap();
+
+        assert_eq!(result, Bytes::from(vec![1]));
+        assert_eq!(gateway.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_are_recorded() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/missing" => CannedResponse::new(404, "not found"),
+            _ => CannedResponse::new(500, "error"),
+        })
+        .await;
+
+        let error = lookup_with(retrying(2), &gateway, &["/down", "/missing"]).await.unwrap_err();
+        let errors = gateway_errors(error);
+
+        // client errors are not retried
+        assert_eq!(gateway.requests().len(), 4);
+        let attempts: Vec<_> = errors
+            .errors()
+            .iter()
+            .map(|error| (error.url.as_str(), error.attempt, error.status))
+            .collect();
+        assert_eq!(
+            attempts,
+            vec![
+                (gateway.url("/down").as_str(), 0, Some(500)),
+                (gateway.url("/down").as_str(), 1, Some(500)),
+                (gateway.url("/down").as_str(), 2, Some(500)),
+                (gateway.url("/missing").as_str(), 0, Some(404)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_retried() {
+        // bind then drop a listener to get a port nothing listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider).with_config(retrying(1));
+
+        let errors = gateway_errors(lookup_via(&middleware, &mock, vec![url]).await.unwrap_err());
+
+        assert_eq!(errors.errors().len(), 2);
+        assert!(errors.errors().iter().all(CCIPRequestError::is_retryable));
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_deadline() {
+        let gateway = TestGateway::start(|_| CannedResponse::new(503, "unavailable")).await;
+        let config = CCIPReadConfig::default()
+            .deadline(Duration::from_secs(5))
+            .retry(
+                RetryPolicy::new(5)
+                    .initial_backoff(Duration::from_secs(60))
+                    .max_backoff(Duration::from_secs(60)),
+            );
+
+        let start = Instant::now();
+        let error = lookup_with(config, &gateway, &["/a", "/b"]).await.unwrap_err();
 
-        Err(CCIPMiddlewareError::GatewayError(error_messages))
+        // no retry fits in the deadline, so every URL is tried once
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(gateway_errors(error).errors().len(), 2);
+        assert_eq!(gateway.requests().len(), 2);
     }
 }
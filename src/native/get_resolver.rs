@@ -1,19 +1,55 @@
-use ethers_core::{abi::ParamType, types::{Address, H160}};
+use std::time::Instant;
+
+use ethers_core::{abi::ParamType, types::{Address, BlockId, H160}};
 use ethers_providers::{Middleware, ENS_ADDRESS, get_resolver};
+use tracing::instrument;
 
-use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware, utils::decode_bytes::decode_bytes};
+use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware, utils::decode_bytes::try_decode_bytes};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     pub async fn get_resolver(&self, ens_name: &str) -> Result<H160, CCIPMiddlewareError<M>> {
+        self.get_resolver_at(ens_name, None).await
+    }
+
+    /// Same as [`get_resolver`](Self::get_resolver), at `block`
+    #[instrument(name = "get_resolver", level = "debug", skip_all, fields(name = %ens_name, block = ?block))]
+    pub async fn get_resolver_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+    ) -> Result<H160, CCIPMiddlewareError<M>> {
+        self.within_deadline(|deadline| self._get_resolver_at(ens_name, block, deadline))
+            .await
+    }
+
+    pub(crate) async fn _get_resolver_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<H160, CCIPMiddlewareError<M>> {
+        let block = self.pin_block(block).await?;
+        let resolver = self.find_resolver(ens_name, block, deadline).await?;
+        self.metrics().resolver_lookup(!resolver.is_zero());
+        Ok(resolver)
+    }
+
+    /// Walks up the parents of `ens_name` until one has a resolver, following ENSIP-10
+    async fn find_resolver(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<H160, CCIPMiddlewareError<M>> {
         let mut current_name: String = ens_name.to_string();
 
         let ens_addr = self.ens.unwrap_or(ENS_ADDRESS);
 
         loop {
-            if current_name.eq("") || current_name.eq(".") {
+            if current_name.is_empty() || current_name.eq(".") {
                 return Ok(H160::zero());
             }
 
@@ -22,22 +58,28 @@ where
             }
 
             let data = self
-                .call(
+                ._call(
                     &get_resolver(ens_addr, &current_name.to_string()).into(),
-                    None,
+                    block,
+                    deadline,
                 )
-                .await.map_err(|x| {
-                    CCIPMiddlewareError::TodoError(format!("GR Error calling resolver: {}", x.to_string()))
+                .await
+                .map_err(|e| CCIPMiddlewareError::ResolverCallError {
+                    resolver: ens_addr,
+                    name: current_name.clone(),
+                    error: Box::new(e),
                 })?;
 
             if data.0.is_empty() {
                 return Ok(H160::zero());
             }
 
-            let resolver_address: Address = decode_bytes(ParamType::Address, data);
+            let resolver_address: Address = try_decode_bytes(ParamType::Address, data)?;
 
             if resolver_address != Address::zero() {
-                if current_name != ens_name && !self.supports_wildcard(resolver_address).await? {
+                if current_name != ens_name
+                    && !self._supports_wildcard_at(resolver_address, block, deadline).await?
+                {
                     return Ok(H160::zero());
                 }
                 return Ok(resolver_address);
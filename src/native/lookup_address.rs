@@ -1,26 +1,147 @@
-use ethers_core::abi::{Address, ParamType};
+use std::time::Instant;
+
+use ethers_core::{
+    abi::{Address, ParamType},
+    types::BlockId,
+};
 use ethers_providers::{reverse_address, Middleware, NAME_SELECTOR};
+use tracing::instrument;
 
 use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     /// Look up an address to find its primary ENS name
     pub async fn lookup_address(&self, address: Address) -> Result<String, CCIPMiddlewareError<M>> {
+        self.lookup_address_at(address, None).await
+    }
+
+    /// Look up an address to find its primary ENS name at `block`
+    #[instrument(name = "lookup_address", level = "debug", skip_all, fields(address = ?address, block = ?block))]
+    pub async fn lookup_address_at(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+    ) -> Result<String, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self._lookup_address_at(address, block, deadline))
+            .await;
+        self.observe("lookup_address", result)
+    }
+
+    async fn _lookup_address_at(
+        &self,
+        address: Address,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<String, CCIPMiddlewareError<M>> {
+        // at a past block, the reverse record and its forward resolution come from
+        // that same block; the latest and pending blocks are followed as they move
+        let block = self.pin_block(block).await?;
         let ens_name = reverse_address(address);
         let domain: String = self
-            .query_resolver(ParamType::String, &ens_name, NAME_SELECTOR)
+            ._query_resolver_parameters_at(
+                ParamType::String,
+                &ens_name,
+                NAME_SELECTOR,
+                None,
+                block,
+                deadline,
+            )
             .await?;
-        let reverse_address = self.resolve_name(&domain).await?;
+        let reverse_address = self._resolve_name_at(&domain, block, deadline).await?;
         if address != reverse_address {
-            Err(CCIPMiddlewareError::TodoError(format!(
-                "User does not own domain: {}",
-                domain
-            )))
+            Err(CCIPMiddlewareError::ReverseRecordError {
+                address,
+                name: domain,
+                resolved: reverse_address,
+            })
         } else {
             Ok(domain)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use ethers_core::{
+        abi::{self, Token},
+        types::{Address, Bytes, H256},
+    };
+    use ethers_providers::{MockResponse, Provider};
+
+    use super::*;
+    use crate::{
+        test_utils::{push_responses, revert, success},
+        transport::{GatewayResponse, GatewayTransport, TransportError},
+        utils::offchain_lookup::OffchainLookup,
+        CCIPReadConfig,
+    };
+
+    /// Answers every request after 300ms
+    #[derive(Debug)]
+    struct SlowGateway;
+
+    #[async_trait]
+    impl GatewayTransport for SlowGateway {
+        async fn request(
+            &self,
+            _sender: Address,
+            _calldata: &[u8],
+            _url: &str,
+        ) -> Result<GatewayResponse, TransportError> {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(GatewayResponse {
+                status: 200,
+                body: br#"{"data":"0xcafe"}"#.to_vec().into(),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// The responses of resolving a name through an offchain resolver
+    fn offchain_resolution(resolver: Address, result: Bytes) -> Vec<MockResponse> {
+        let lookup = OffchainLookup {
+            sender: resolver,
+            urls: vec!["https://gateway.example/{sender}/{data}.json".to_string()],
+            ..Default::default()
+        };
+        vec![
+            success(H256::from(resolver).as_bytes()),
+            // no ERC-165
+            revert(&[]),
+            revert(&lookup.encode()),
+            success(&result),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_deadline_covers_the_whole_lookup() {
+        let address = Address::repeat_byte(2);
+        let resolver = Address::repeat_byte(1);
+        let name = abi::encode(&[Token::String("nick.eth".to_string())]);
+        let responses = [
+            offchain_resolution(resolver, name.into()),
+            offchain_resolution(resolver, H256::from(address).as_bytes().to_vec().into()),
+        ];
+
+        // each of the two resolutions fits in the deadline, both don't
+        let (provider, mock) = Provider::mocked();
+        push_responses(&mock, responses.concat());
+        let middleware = CCIPReadMiddleware::new(provider).with_transport(SlowGateway);
+        assert_eq!(middleware.lookup_address(address).await.unwrap(), "nick.eth");
+
+        let (provider, mock) = Provider::mocked();
+        push_responses(&mock, responses.concat());
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().deadline(Duration::from_millis(500)))
+            .with_transport(SlowGateway);
+        let error = middleware.lookup_address(address).await.unwrap_err();
+        assert!(matches!(error, CCIPMiddlewareError::DeadlineError(_)), "{:?}", error);
+    }
+}
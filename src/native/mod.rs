@@ -4,6 +4,7 @@ pub mod resolve_name;
 pub mod get_resolver;
 pub mod resolve_field;
 pub mod resolve_avatar;
+pub mod resolve_nft;
 pub mod lookup_address;
 pub mod query_resolver;
 pub mod resolve_addresses;
@@ -1,14 +1,15 @@
 use ethers_core::{
     abi::{ParamType, Detokenize},
-    types::{Selector},
+    types::{BlockId, Selector},
 };
 use ethers_providers::{Middleware};
+use tracing::instrument;
 
 use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     pub async fn query_resolver<T: Detokenize>(
         &self,
@@ -16,7 +17,20 @@ where
         ens_name: &str,
         selector: Selector,
     ) -> Result<T, CCIPMiddlewareError<M>> {
-        self.query_resolver_parameters(param, ens_name, selector, None)
+        self.query_resolver_at(param, ens_name, selector, None)
+            .await
+    }
+
+    /// Same as [`query_resolver`](Self::query_resolver), at `block`
+    #[instrument(name = "query_resolver", level = "debug", skip_all, fields(name = %ens_name, block = ?block))]
+    pub async fn query_resolver_at<T: Detokenize>(
+        &self,
+        param: ParamType,
+        ens_name: &str,
+        selector: Selector,
+        block: Option<BlockId>,
+    ) -> Result<T, CCIPMiddlewareError<M>> {
+        self.query_resolver_parameters_at(param, ens_name, selector, None, block)
             .await
     }
 }
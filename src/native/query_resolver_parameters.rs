@@ -1,19 +1,21 @@
+use std::time::Instant;
+
 use ethers_core::{
     abi::{self, Detokenize, ParamType, Token},
-    types::{transaction::eip2718::TypedTransaction, Bytes, Selector},
+    types::{transaction::eip2718::TypedTransaction, BlockId, Bytes, Selector},
 };
 use ethers_providers::{resolve, Middleware};
-use tracing::debug;
+use tracing::{field::Empty, instrument, Span};
 
 use crate::{
     error::CCIPMiddlewareError,
-    utils::{decode_bytes::decode_bytes, dns_encode::dns_encode},
+    utils::{decode_bytes::try_decode_bytes, dns_encode::dns_encode},
     CCIPReadMiddleware,
 };
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     pub async fn query_resolver_parameters<T: Detokenize>(
         &self,
@@ -22,16 +24,60 @@ where
         selector: Selector,
         parameters: Option<&[u8]>,
     ) -> Result<T, CCIPMiddlewareError<M>> {
-        let resolver_address = self.get_resolver(ens_name).await?;
+        self.query_resolver_parameters_at(param, ens_name, selector, parameters, None)
+            .await
+    }
+
+    /// Same as [`query_resolver_parameters`](Self::query_resolver_parameters), at `block`
+    #[instrument(
+        name = "query_resolver_parameters",
+        level = "debug",
+        skip_all,
+        fields(name = %ens_name, block = ?block, resolver = Empty, wildcard = Empty)
+    )]
+    pub async fn query_resolver_parameters_at<T: Detokenize>(
+        &self,
+        param: ParamType,
+        ens_name: &str,
+        selector: Selector,
+        parameters: Option<&[u8]>,
+        block: Option<BlockId>,
+    ) -> Result<T, CCIPMiddlewareError<M>> {
+        self.within_deadline(|deadline| {
+            self._query_resolver_parameters_at(
+                param, ens_name, selector, parameters, block, deadline,
+            )
+        })
+        .await
+    }
+
+    pub(crate) async fn _query_resolver_parameters_at<T: Detokenize>(
+        &self,
+        param: ParamType,
+        ens_name: &str,
+        selector: Selector,
+        parameters: Option<&[u8]>,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<T, CCIPMiddlewareError<M>> {
+        // every call of the resolution sees the same block
+        let block = self.pin_block(block).await?;
+        let resolver_address = self._get_resolver_at(ens_name, block, deadline).await?;
+        Span::current().record("resolver", tracing::field::debug(resolver_address));
 
         let mut tx: TypedTransaction =
             resolve(resolver_address, selector, ens_name, parameters).into();
 
         let mut parse_bytes = false;
-        if self.supports_wildcard(resolver_address).await? {
+        if self._supports_wildcard_at(resolver_address, block, deadline).await? {
             parse_bytes = true;
 
-            let dns_encode_token = Token::Bytes(dns_encode(ens_name).unwrap());
+            let dns_encoded =
+                dns_encode(ens_name).map_err(|reason| CCIPMiddlewareError::InvalidNameError {
+                    name: ens_name.to_string(),
+                    reason,
+                })?;
+            let dns_encode_token = Token::Bytes(dns_encoded);
             let tx_data_token = Token::Bytes(tx.data().unwrap().to_vec());
 
             let tokens = vec![dns_encode_token, tx_data_token];
@@ -46,17 +92,51 @@ where
             ));
         }
 
-        println!("Calling resolver with tx: {:?} at res {:?}", tx, resolver_address);
+        Span::current().record("wildcard", parse_bytes);
+        tracing::trace!(?tx, "calling resolver");
 
         // resolve
-        let mut data = self.call(&tx, None).await.map_err(|e| {
-            CCIPMiddlewareError::TodoError(format!("QRP Error calling resolver: {}", e.to_string()))
+        let mut data = self._call(&tx, block, deadline).await.map_err(|e| {
+            CCIPMiddlewareError::ResolverCallError {
+                resolver: resolver_address,
+                name: ens_name.to_string(),
+                error: Box::new(e),
+            }
         })?;
 
         if parse_bytes {
-            data = decode_bytes(ParamType::Bytes, data);
+            data = try_decode_bytes(ParamType::Bytes, data)?;
         }
 
-        Ok(decode_bytes(param, data))
+        Ok(try_decode_bytes(param, data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::types::{Address, H256, U256};
+    use ethers_providers::Provider;
+
+    use super::*;
+    use crate::test_utils::{push_responses, success};
+
+    #[tokio::test]
+    async fn test_invalid_wildcard_name() {
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        let resolver = H256::from(Address::repeat_byte(1));
+        let supported = H256::from_low_u64_be(1);
+        push_responses(&mock, vec![success(resolver.as_bytes()), success(supported.as_bytes())]);
+
+        let name = format!("{}.eth", "a".repeat(64));
+        let error = middleware
+            .query_resolver::<U256>(ParamType::Uint(256), &name, [0x3b, 0x3b, 0x57, 0xde])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, CCIPMiddlewareError::InvalidNameError { name: n, .. } if *n == name),
+            "{:?}",
+            error
+        );
     }
 }
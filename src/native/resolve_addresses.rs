@@ -1,8 +1,9 @@
 use ethers_core::{
     abi::ParamType,
-    types::{Bytes, U256},
+    types::{BlockId, Bytes, U256},
 };
-use ethers_providers::{reverse_address, Middleware, NAME_SELECTOR};
+use ethers_providers::Middleware;
+use tracing::instrument;
 
 use crate::{
     error::CCIPMiddlewareError, utils::selectors::ADDR_MULTI_SELECTOR2, CCIPReadMiddleware,
@@ -10,19 +11,33 @@ use crate::{
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     pub async fn resolve_addresses(
         &self,
         ens_name: &str,
         coin_type: &str,
     ) -> Result<String, CCIPMiddlewareError<M>> {
-        let x = U256::from_dec_str(coin_type).map_err(|x| {
-            CCIPMiddlewareError::TodoError("FetchError(Invalid Cointype)".to_owned())
+        self.resolve_addresses_at(ens_name, coin_type, None).await
+    }
+
+    /// Same as [`resolve_addresses`](Self::resolve_addresses), at `block`
+    #[instrument(name = "resolve_addresses", level = "debug", skip_all, fields(name = %ens_name, coin_type = %coin_type, block = ?block))]
+    pub async fn resolve_addresses_at(
+        &self,
+        ens_name: &str,
+        coin_type: &str,
+        block: Option<BlockId>,
+    ) -> Result<String, CCIPMiddlewareError<M>> {
+        let _coin_type = U256::from_dec_str(coin_type).map_err(|source| {
+            CCIPMiddlewareError::InvalidCoinTypeError {
+                coin_type: coin_type.to_string(),
+                source,
+            }
         })?;
 
         let field: Bytes = self
-            .query_resolver_parameters(
+            .query_resolver_parameters_at(
                 ParamType::Bytes,
                 ens_name,
                 ADDR_MULTI_SELECTOR2,
@@ -30,6 +45,7 @@ where
                     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                     0, 0, 0, 0, 0, 60,
                 ]),
+                block,
             )
             .await?;
         Ok(format!("{:?}", field))
@@ -1,34 +1,56 @@
-use std::str::FromStr;
+use std::{str::FromStr, time::Instant};
 
 use ethers_core::{
     abi::ParamType,
-    types::{Address, NameOrAddress, TransactionRequest},
+    types::{Address, BlockId, NameOrAddress, TransactionRequest},
 };
 use ethers_providers::{erc, Middleware};
 use futures_util::try_join;
 use reqwest::Url;
+use tracing::instrument;
 
-use crate::{error::CCIPMiddlewareError, utils::decode_bytes::decode_bytes, CCIPReadMiddleware};
+use crate::{error::CCIPMiddlewareError, utils::decode_bytes::try_decode_bytes, CCIPReadMiddleware};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     /// Resolve avatar field of an ENS name
     pub async fn resolve_avatar(&self, ens_name: &str) -> Result<Url, CCIPMiddlewareError<M>> {
+        self.resolve_avatar_at(ens_name, None).await
+    }
+
+    /// Resolve avatar field of an ENS name at `block`, the NFT ownership being checked at the same block
+    #[instrument(name = "resolve_avatar", level = "debug", skip_all, fields(name = %ens_name, block = ?block))]
+    pub async fn resolve_avatar_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+    ) -> Result<Url, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self._resolve_avatar_at(ens_name, block, deadline))
+            .await;
+        self.observe("resolve_avatar", result)
+    }
+
+    async fn _resolve_avatar_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<Url, CCIPMiddlewareError<M>> {
+        let block = self.pin_block(block).await?;
         let (field, owner) = try_join!(
-            self.resolve_field(ens_name, "avatar"),
-            self.resolve_name(ens_name)
+            self._resolve_field_at(ens_name, "avatar", block, deadline),
+            self._resolve_name_at(ens_name, block, deadline)
         )?;
-        let url = Url::from_str(&field).map_err(|e| {
-            CCIPMiddlewareError::TodoError(format!("URLParseError {}", e.to_string()))
-        })?;
+        let url = Url::from_str(&field)?;
         match url.scheme() {
             "https" | "data" => Ok(url),
-            "ipfs" => erc::http_link_ipfs(url).map_err(CCIPMiddlewareError::TodoError),
+            "ipfs" => erc::http_link_ipfs(url).map_err(CCIPMiddlewareError::NFTError),
             "eip155" => {
                 let token =
-                    erc::ERCNFT::from_str(url.path()).map_err(CCIPMiddlewareError::TodoError)?;
+                    erc::ERCNFT::from_str(url.path()).map_err(CCIPMiddlewareError::NFTError)?;
                 match token.type_ {
                     erc::ERCNFTType::ERC721 => {
                         let tx = TransactionRequest {
@@ -38,17 +60,13 @@ where
                             to: Some(NameOrAddress::Address(token.contract)),
                             ..Default::default()
                         };
-                        let data = self.call(&tx.into(), None).await.map_err(|x| {
-                            CCIPMiddlewareError::TodoError(format!(
-                                "Error calling nft info: {}",
-                                x.to_string()
-                            ))
-                        })?;
+                        let data = self._call(&tx.into(), block, deadline).await?;
 
-                        if decode_bytes::<Address>(ParamType::Address, data) != owner {
-                            return Err(CCIPMiddlewareError::TodoError(
-                                "NFTOwnerError".to_string(),
-                            ));
+                        if try_decode_bytes::<Address>(ParamType::Address, data)? != owner {
+                            return Err(CCIPMiddlewareError::NFTOwnerError {
+                                contract: token.contract,
+                                owner,
+                            });
                         }
                     }
                     erc::ERCNFTType::ERC1155 => {
@@ -66,38 +84,29 @@ where
                             to: Some(NameOrAddress::Address(token.contract)),
                             ..Default::default()
                         };
-                        let data = self.call(&tx.into(), None).await.map_err(|x| {
-                            CCIPMiddlewareError::TodoError(format!(
-                                "Error calling nft info: {}",
-                                x.to_string()
-                            ))
-                        })?;
-                        if decode_bytes::<u64>(ParamType::Uint(64), data) == 0 {
-                            return Err(CCIPMiddlewareError::TodoError(
-                                "Incorrect Balance".to_string(),
-                            ));
+                        let data = self._call(&tx.into(), block, deadline).await?;
+                        if try_decode_bytes::<u64>(ParamType::Uint(64), data)? == 0 {
+                            return Err(CCIPMiddlewareError::NFTOwnerError {
+                                contract: token.contract,
+                                owner,
+                            });
                         }
                     }
                 }
 
-                let image_url = self.inner().resolve_nft(token).await.map_err(|x| {
-                    CCIPMiddlewareError::TodoError(format!(
-                        "Error resolving nft: {}",
-                        x.to_string()
-                    ))
-                })?;
+                let image_url = self._resolve_nft_at(token, block, deadline).await?;
                 match image_url.scheme() {
                     "https" | "data" => Ok(image_url),
-                    "ipfs" => erc::http_link_ipfs(image_url).map_err(|x| {
-                        CCIPMiddlewareError::TodoError(format!("URLParseError {}", x))
-                    }),
-                    _ => Err(CCIPMiddlewareError::TodoError(
-                        "UnsupportedURLSchemeError".to_string(),
+                    "ipfs" => {
+                        erc::http_link_ipfs(image_url).map_err(CCIPMiddlewareError::NFTError)
+                    }
+                    scheme => Err(CCIPMiddlewareError::UnsupportedURLSchemeError(
+                        scheme.to_string(),
                     )),
                 }
             }
-            _ => Err(CCIPMiddlewareError::TodoError(
-                "UnsupportedURLSchemeError".to_string(),
+            scheme => Err(CCIPMiddlewareError::UnsupportedURLSchemeError(
+                scheme.to_string(),
             )),
         }
     }
@@ -1,11 +1,14 @@
-use ethers_core::abi::ParamType;
+use std::time::Instant;
+
+use ethers_core::{abi::ParamType, types::BlockId};
 use ethers_providers::{parameterhash, Middleware, FIELD_SELECTOR};
+use tracing::instrument;
 
 use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     /// Resolve a field of an ENS name
     pub async fn resolve_field(
@@ -13,14 +16,38 @@ where
         ens_name: &str,
         field: &str,
     ) -> Result<String, CCIPMiddlewareError<M>> {
-        let field: String = self
-            .query_resolver_parameters(
-                ParamType::String,
-                ens_name,
-                FIELD_SELECTOR,
-                Some(&parameterhash(field)),
-            )
-            .await?;
-        Ok(field)
+        self.resolve_field_at(ens_name, field, None).await
+    }
+
+    /// Resolve a field of an ENS name at `block`
+    #[instrument(name = "resolve_field", level = "debug", skip_all, fields(name = %ens_name, field = %field, block = ?block))]
+    pub async fn resolve_field_at(
+        &self,
+        ens_name: &str,
+        field: &str,
+        block: Option<BlockId>,
+    ) -> Result<String, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self._resolve_field_at(ens_name, field, block, deadline))
+            .await;
+        self.observe("resolve_field", result)
+    }
+
+    pub(crate) async fn _resolve_field_at(
+        &self,
+        ens_name: &str,
+        field: &str,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<String, CCIPMiddlewareError<M>> {
+        self._query_resolver_parameters_at(
+            ParamType::String,
+            ens_name,
+            FIELD_SELECTOR,
+            Some(&parameterhash(field)),
+            block,
+            deadline,
+        )
+        .await
     }
 }
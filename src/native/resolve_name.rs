@@ -1,18 +1,50 @@
+use std::time::Instant;
+
 use ethers_core::{
     abi::ParamType,
-    types::Address,
+    types::{Address, BlockId},
 };
 use ethers_providers::{Middleware, ADDR_SELECTOR};
+use tracing::instrument;
 
 use crate::{error::CCIPMiddlewareError, CCIPReadMiddleware};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     /// Resolve an ENS name to an address
     pub async fn resolve_name(&self, ens_name: &str) -> Result<Address, CCIPMiddlewareError<M>> {
-        self.query_resolver(ParamType::Address, ens_name, ADDR_SELECTOR)
-            .await
+        self.resolve_name_at(ens_name, None).await
+    }
+
+    /// Same as [`resolve_name`](Self::resolve_name), at `block`
+    #[instrument(name = "resolve_name", level = "debug", skip_all, fields(name = %ens_name, block = ?block))]
+    pub async fn resolve_name_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+    ) -> Result<Address, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self._resolve_name_at(ens_name, block, deadline))
+            .await;
+        self.observe("resolve_name", result)
+    }
+
+    pub(crate) async fn _resolve_name_at(
+        &self,
+        ens_name: &str,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<Address, CCIPMiddlewareError<M>> {
+        self._query_resolver_parameters_at(
+            ParamType::Address,
+            ens_name,
+            ADDR_SELECTOR,
+            None,
+            block,
+            deadline,
+        )
+        .await
     }
 }
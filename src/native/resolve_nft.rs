@@ -0,0 +1,146 @@
+use std::time::Instant;
+
+use ethers_core::{
+    abi::ParamType,
+    types::{BlockId, NameOrAddress, TransactionRequest},
+};
+use ethers_providers::{erc, Middleware};
+use reqwest::Url;
+use tracing::instrument;
+
+use crate::{
+    error::CCIPMiddlewareError, transport::read_body, utils::decode_bytes::try_decode_bytes,
+    CCIPReadMiddleware,
+};
+
+impl<M> CCIPReadMiddleware<M>
+where
+    M: Middleware + 'static,
+{
+    /// Resolve the image URL of an ERC721/ERC1155 token
+    ///
+    /// Same as the provider implementation, except that the `tokenURI`/`uri`
+    /// call goes through CCIP-Read, and that the metadata URL is checked against
+    /// the gateway policy and its response bounded by the maximum response size.
+    pub async fn resolve_nft(&self, token: erc::ERCNFT) -> Result<Url, CCIPMiddlewareError<M>> {
+        self.resolve_nft_at(token, None).await
+    }
+
+    /// Resolve the image URL of an ERC721/ERC1155 token, with its URI at `block`
+    #[instrument(name = "resolve_nft", level = "debug", skip_all, fields(contract = ?token.contract, block = ?block))]
+    pub async fn resolve_nft_at(
+        &self,
+        token: erc::ERCNFT,
+        block: Option<BlockId>,
+    ) -> Result<Url, CCIPMiddlewareError<M>> {
+        let result = self
+            .within_deadline(|deadline| self._resolve_nft_at(token, block, deadline))
+            .await;
+        self.observe("resolve_nft", result)
+    }
+
+    pub(crate) async fn _resolve_nft_at(
+        &self,
+        token: erc::ERCNFT,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
+    ) -> Result<Url, CCIPMiddlewareError<M>> {
+        let selector = token.type_.resolution_selector();
+        let tx = TransactionRequest {
+            data: Some([&selector[..], &token.id].concat().into()),
+            to: Some(NameOrAddress::Address(token.contract)),
+            ..Default::default()
+        };
+        let data = self._call(&tx.into(), block, deadline).await?;
+        let mut metadata_url = Url::parse(&try_decode_bytes::<String>(ParamType::String, data)?)?;
+
+        if token.type_ == erc::ERCNFTType::ERC1155 {
+            metadata_url.set_path(&metadata_url.path().replace("%7Bid%7D", &hex::encode(token.id)));
+        }
+        if metadata_url.scheme() == "ipfs" {
+            metadata_url = erc::http_link_ipfs(metadata_url).map_err(CCIPMiddlewareError::NFTError)?;
+        }
+
+        // the URL comes from the contract, it is held to the same rules as gateway URLs
+        self.config()
+            .gateway_policy
+            .check(metadata_url.as_str())
+            .await
+            .map_err(CCIPMiddlewareError::GatewayPolicyError)?;
+        let response = self.client().get(metadata_url).send().await?;
+        let limit = self.config().max_response_size;
+        let body = read_body::<CCIPMiddlewareError<M>>(response, limit).await?;
+        let metadata: erc::Metadata = serde_json::from_slice(&body)
+            .map_err(|e| CCIPMiddlewareError::NFTError(format!("Invalid metadata: {}", e)))?;
+        Ok(Url::parse(&metadata.image)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::abi::{self, Token};
+    use ethers_providers::Provider;
+
+    use super::*;
+    use crate::{
+        policy::{GatewayPolicy, PolicyViolation},
+        test_utils::{push_responses, success, CannedResponse, TestGateway},
+        transport::ResponseTooLarge,
+        CCIPReadConfig,
+    };
+
+    fn token() -> erc::ERCNFT {
+        "erc721:0x0101010101010101010101010101010101010101/1".parse().unwrap()
+    }
+
+    fn token_uri(uri: &str) -> Vec<u8> {
+        abi::encode(&[Token::String(uri.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_metadata_url_is_checked() {
+        let (provider, mock) = Provider::mocked();
+        let policy = GatewayPolicy::default().block_private_ips(true);
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().gateway_policy(policy));
+        let uri = token_uri("http://169.254.169.254/latest/meta-data");
+        push_responses(&mock, vec![success(&uri)]);
+
+        let error = middleware.resolve_nft(token()).await.unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                CCIPMiddlewareError::GatewayPolicyError(PolicyViolation::PrivateAddress { .. })
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_size_is_limited() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/small" => CannedResponse::new(200, r#"{"image":"https://example.com/1.png"}"#),
+            _ => CannedResponse::new(200, format!(r#"{{"image":"{}"}}"#, "a".repeat(1024))),
+        })
+        .await;
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().max_response_size(256));
+
+        push_responses(&mock, vec![success(&token_uri(&gateway.url("/small")))]);
+        let image = middleware.resolve_nft(token()).await.unwrap();
+        assert_eq!(image.as_str(), "https://example.com/1.png");
+
+        push_responses(&mock, vec![success(&token_uri(&gateway.url("/large")))]);
+        let error = middleware.resolve_nft(token()).await.unwrap_err();
+        assert!(
+            matches!(
+                error,
+                CCIPMiddlewareError::ResponseTooLargeError(ResponseTooLarge { limit: 256, .. })
+            ),
+            "{:?}",
+            error
+        );
+    }
+}
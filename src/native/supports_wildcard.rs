@@ -1,14 +1,17 @@
+use std::time::Instant;
+
 use ethers_core::{
     abi::ParamType,
-    types::{H160, TransactionRequest, NameOrAddress, Bytes, U256},
+    types::{BlockId, H160, TransactionRequest, NameOrAddress, Bytes, U256},
 };
 use ethers_providers::{Middleware};
+use tracing::instrument;
 
-use crate::{CCIPReadMiddleware, error::CCIPMiddlewareError, utils::decode_bytes::decode_bytes};
+use crate::{CCIPReadMiddleware, error::CCIPMiddlewareError, utils::decode_bytes::try_decode_bytes};
 
 impl<M> CCIPReadMiddleware<M>
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
         /// The supports_wildcard checks if a given resolver supports the wildcard resolution by calling
     /// its `supportsInterface` function with the `resolve(bytes,bytes)` selector.
@@ -24,6 +27,28 @@ where
     pub async fn supports_wildcard(
         &self,
         resolver_address: H160,
+    ) -> Result<bool, CCIPMiddlewareError<M>> {
+        self.supports_wildcard_at(resolver_address, None).await
+    }
+
+    /// Same as [`supports_wildcard`](Self::supports_wildcard), at `block`
+    #[instrument(name = "supports_wildcard", level = "debug", skip_all, fields(resolver = ?resolver_address, block = ?block))]
+    pub async fn supports_wildcard_at(
+        &self,
+        resolver_address: H160,
+        block: Option<BlockId>,
+    ) -> Result<bool, CCIPMiddlewareError<M>> {
+        self.within_deadline(|deadline| {
+            self._supports_wildcard_at(resolver_address, block, deadline)
+        })
+        .await
+    }
+
+    pub(crate) async fn _supports_wildcard_at(
+        &self,
+        resolver_address: H160,
+        block: Option<BlockId>,
+        deadline: Option<Instant>,
     ) -> Result<bool, CCIPMiddlewareError<M>> {
         // Prepare the data for the `supportsInterface` call, providing the selector for
         // the "resolve(bytes,bytes)" function
@@ -39,23 +64,26 @@ where
             ..Default::default()
         };
 
-        let _tx_result: Result<Bytes, _> = self.call(&_tx_request.into(), None).await;
+        let _tx_result: Result<Bytes, _> = self._call(&_tx_request.into(), block, deadline).await;
         let _tx = match _tx_result {
             Ok(_tx) => _tx,
-            Err(_error) => {
-                println!("Error calling: {:?}", _error);
+            Err(error) => {
+                tracing::debug!(%error, "supportsInterface call failed");
                 Bytes::from([])
             }
         };
 
         // If the response is empty, the resolver does not support wildcard resolution
         if _tx.0.is_empty() {
+            self.metrics().wildcard_check(false);
             return Ok(false);
         }
 
-        let _result: U256 = decode_bytes(ParamType::Uint(256), _tx);
+        let _result: U256 = try_decode_bytes(ParamType::Uint(256), _tx)?;
 
         // If the result is one, the resolver supports wildcard resolution; otherwise, it does not
-        Ok(_result.eq(&U256::one()))
+        let supported = _result.eq(&U256::one());
+        self.metrics().wildcard_check(supported);
+        Ok(supported)
     }
 }
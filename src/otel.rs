@@ -0,0 +1,151 @@
+//! OpenTelemetry trace context propagation to gateways, enabled by the `otel` feature
+//!
+//! [`ReqwestTransport`](crate::transport::ReqwestTransport) sends every gateway
+//! request in a client span following the OpenTelemetry HTTP semantic
+//! conventions, and injects its W3C `traceparent` and `tracestate` headers so
+//! that the gateway spans join the trace of the call. The headers are built by
+//! the global propagator, which the application sets up along with its
+//! `tracing-opentelemetry` layer:
+//!
+//! ```ignore
+//! opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+//! ```
+use opentelemetry::{global, propagation::Injector};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::{field::Empty, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{
+    transport::{GatewayResponse, TransportError},
+    utils::gateway_request::GatewayRequest,
+};
+
+/// The trace context headers of the current span, for custom transports
+pub fn context_headers() -> HeaderMap {
+    let context = Span::current().context();
+    let mut headers = HeaderMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// The client span of a gateway request
+pub(crate) fn client_span(request: &GatewayRequest) -> Span {
+    let method = match request {
+        GatewayRequest::Get { .. } => "GET",
+        GatewayRequest::Post { .. } => "POST",
+    };
+    let url = request.url();
+    // credentials must never end up in traces
+    let mut full_url = url.clone();
+    let _ = full_url.set_username("");
+    let _ = full_url.set_password(None);
+
+    tracing::debug_span!(
+        "http_request",
+        otel.name = method,
+        otel.kind = "client",
+        otel.status_code = Empty,
+        http.request.method = method,
+        url.full = full_url.as_str(),
+        server.address = url.host_str().unwrap_or_default(),
+        server.port = url.port_or_known_default(),
+        http.response.status_code = Empty,
+        error.type = Empty,
+    )
+}
+
+/// Records the status of the response on its client span, 4xx and 5xx being errors
+pub(crate) fn record_result(span: &Span, result: &Result<GatewayResponse, TransportError>) {
+    let error_type = match result {
+        Ok(response) => {
+            span.record("http.response.status_code", response.status);
+            if response.status < 400 {
+                return;
+            }
+            response.status.to_string()
+        }
+        Err(_) => "_OTHER".to_string(),
+    };
+    span.record("error.type", error_type.as_str());
+    span.record("otel.status_code", "ERROR");
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest};
+    use ethers_providers::Provider;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::TracerProvider};
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{
+        test_utils::{lookup_via, CannedResponse, SpanRecorder, TestGateway},
+        CCIPReadMiddleware,
+    };
+
+    #[tokio::test]
+    async fn test_trace_context_is_propagated() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let gateway = TestGateway::start(|_| CannedResponse::data(&[0xca, 0xfe])).await;
+
+        let root = tracing::info_span!("resolve");
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider);
+        lookup_via(&middleware, &mock, vec![gateway.url("/lookup")])
+            .instrument(root.clone())
+            .await
+            .unwrap();
+
+        let trace_id = root.context().span().span_context().trace_id();
+        let traceparent = gateway.requests()[0].header("traceparent").unwrap().to_string();
+        assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+    }
+
+    #[tokio::test]
+    async fn test_client_span() {
+        let gateway = TestGateway::start(|_| CannedResponse::new(500, "down")).await;
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let url = gateway.url("/{sender}/{data}.json");
+        let tx: TypedTransaction = TransactionRequest::new().to(Address::zero()).into();
+        let (provider, _) = Provider::mocked();
+        CCIPReadMiddleware::new(provider)
+            ._ccip_request(Address::zero(), &tx, &[], vec![&url], None)
+            .await
+            .unwrap_err();
+
+        let field = |name| recorder.field("http_request", name);
+        assert_eq!(field("otel.kind"), Some("client".to_string()));
+        assert_eq!(field("http.request.method"), Some("GET".to_string()));
+        assert_eq!(
+            field("url.full"),
+            Some(gateway.url("/0x0000000000000000000000000000000000000000/0x.json"))
+        );
+        assert_eq!(field("http.response.status_code"), Some("500".to_string()));
+        assert_eq!(field("error.type"), Some("500".to_string()));
+        assert_eq!(field("otel.status_code"), Some("ERROR".to_string()));
+    }
+}
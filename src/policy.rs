@@ -0,0 +1,438 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use thiserror::Error;
+use url::{Host, Url};
+
+use crate::host_options::HostOptions;
+
+/// Reasons for a gateway URL to be rejected by a [`GatewayPolicy`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    #[error("Invalid gateway URL: {0}")]
+    InvalidURL(#[from] url::ParseError),
+
+    #[error("URL scheme {0} is not allowed")]
+    Scheme(String),
+
+    #[error("Host {0} is not allowed")]
+    Host(String),
+
+    #[error("Host {host} resolves to non-public address {ip}")]
+    PrivateAddress { host: String, ip: IpAddr },
+
+    #[error("Host {0} could not be resolved")]
+    Unresolvable(String),
+
+    #[error("{count} gateway URLs exceed the limit of {max}")]
+    TooManyURLs { count: usize, max: usize },
+}
+
+/// Restrictions on the gateway URLs fetched by the middleware
+///
+/// Gateway URLs come from contract revert data, so a malicious resolver can
+/// point the middleware at any host. Every URL of an `OffchainLookup` is
+/// checked before any request is made; rejected URLs are skipped and recorded
+/// in the gateway errors.
+///
+/// The default policy allows everything, [`GatewayPolicy::strict`] only allows
+/// HTTPS gateways on public addresses.
+///
+/// When private addresses are blocked, the HTTP clients built by the
+/// middleware also resolve every host, redirect targets included, with a
+/// [`PublicResolver`], so that a host can't pass the check and then resolve to
+/// a private address when connecting. A client set with
+/// [`CCIPReadConfig::client`](crate::CCIPReadConfig::client) needs it too.
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::{policy::GatewayPolicy, CCIPReadConfig};
+///
+/// let config = CCIPReadConfig::default().gateway_policy(
+///     GatewayPolicy::strict()
+///         .allow_host("*.ens.domains")
+///         .max_urls(4),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GatewayPolicy {
+    /// Only allow `https` gateway URLs
+    pub https_only: bool,
+    /// Reject hosts resolving to loopback, private, link-local or otherwise non-public addresses
+    pub block_private_ips: bool,
+    /// When set, only these hosts are allowed. `*.example.com` matches any subdomain.
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Hosts that are never allowed. `*.example.com` matches any subdomain.
+    pub denied_hosts: Vec<String>,
+    /// Maximum number of HTTP redirects followed by a gateway request
+    pub max_http_redirects: Option<usize>,
+    /// Maximum number of URLs in a single `OffchainLookup`
+    pub max_urls: Option<usize>,
+}
+
+impl GatewayPolicy {
+    /// HTTPS only, no private addresses, at most 3 HTTP redirects
+    pub fn strict() -> Self {
+        Self {
+            https_only: true,
+            block_private_ips: true,
+            max_http_redirects: Some(3),
+            ..Default::default()
+        }
+    }
+
+    pub fn https_only(mut self, https_only: bool) -> Self {
+        self.https_only = https_only;
+        self
+    }
+
+    pub fn block_private_ips(mut self, block_private_ips: bool) -> Self {
+        self.block_private_ips = block_private_ips;
+        self
+    }
+
+    pub fn allow_host<T: Into<String>>(mut self, host: T) -> Self {
+        self.allowed_hosts
+            .get_or_insert_with(Vec::new)
+            .push(host.into());
+        self
+    }
+
+    pub fn deny_host<T: Into<String>>(mut self, host: T) -> Self {
+        self.denied_hosts.push(host.into());
+        self
+    }
+
+    pub fn max_http_redirects(mut self, max_http_redirects: usize) -> Self {
+        self.max_http_redirects = Some(max_http_redirects);
+        self
+    }
+
+    pub fn max_urls(mut self, max_urls: usize) -> Self {
+        self.max_urls = Some(max_urls);
+        self
+    }
+
+    /// Checks the number of URLs of an `OffchainLookup`
+    pub fn check_url_count(&self, count: usize) -> Result<(), PolicyViolation> {
+        match self.max_urls {
+            Some(max) if count > max => Err(PolicyViolation::TooManyURLs { count, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a gateway URL, resolving its host if private addresses are blocked
+    pub async fn check(&self, url: &str) -> Result<(), PolicyViolation> {
+        let url = Url::parse(url)?;
+        self.check_static(&url)?;
+
+        if self.block_private_ips {
+            if let Some(Host::Domain(domain)) = url.host() {
+                let port = url.port_or_known_default().unwrap_or(443);
+                let addrs = tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|_| PolicyViolation::Unresolvable(domain.to_string()))?;
+                for addr in addrs {
+                    check_ip(domain, addr.ip())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks everything that does not need DNS resolution
+    fn check_static(&self, url: &Url) -> Result<(), PolicyViolation> {
+        if self.https_only && url.scheme() != "https" {
+            return Err(PolicyViolation::Scheme(url.scheme().to_string()));
+        }
+
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if self.denied_hosts.iter().any(|pattern| host_matches(pattern, host)) {
+            return Err(PolicyViolation::Host(host.to_string()));
+        }
+        if let Some(allowed) = &self.allowed_hosts {
+            if !allowed.iter().any(|pattern| host_matches(pattern, host)) {
+                return Err(PolicyViolation::Host(host.to_string()));
+            }
+        }
+
+        if self.block_private_ips {
+            match url.host() {
+                Some(Host::Ipv4(ip)) => check_ip(host, IpAddr::V4(ip))?,
+                Some(Host::Ipv6(ip)) => check_ip(host, IpAddr::V6(ip))?,
+                Some(Host::Domain(_)) => {}
+                None => return Err(PolicyViolation::Host(host.to_string())),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The redirect policy for HTTP clients, enforcing the redirect limit and
+    /// checking every redirect target against the policy
+    ///
+    /// Redirect targets are not resolved here: hostnames resolving to private
+    /// addresses are caught by the [`PublicResolver`] of the client.
+    pub fn redirect_policy(&self) -> reqwest::redirect::Policy {
+        self.redirect_policy_within(None)
+    }
+
+    /// Like [`GatewayPolicy::redirect_policy`], also refusing redirects to URLs
+    /// `options` do not apply to, so that per-host credentials stay on that host
+    pub(crate) fn redirect_policy_within(&self, options: Option<&HostOptions>) -> reqwest::redirect::Policy {
+        let policy = self.clone();
+        // only what the check needs, not the credentials
+        let scope = options.map(|options| {
+            HostOptions::new(options.pattern.clone()).allow_http(options.allow_http)
+        });
+        reqwest::redirect::Policy::custom(move |attempt| {
+            let max = policy.max_http_redirects.unwrap_or(10);
+            if attempt.previous().len() > max {
+                return attempt.error(format!("More than {} redirects", max));
+            }
+            if let Some(scope) = &scope {
+                if !scope.applies_to(attempt.url()) {
+                    let host = attempt.url().host_str().unwrap_or_default().to_ascii_lowercase();
+                    return attempt.error(PolicyViolation::Host(host));
+                }
+            }
+            match policy.check_static(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(violation) => attempt.error(violation),
+            }
+        })
+    }
+}
+
+/// A DNS resolver for `reqwest` clients dropping the addresses rejected by
+/// [`GatewayPolicy::block_private_ips`], failing when none is left
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use ethers_ccip_read::{policy::{GatewayPolicy, PublicResolver}, CCIPReadConfig};
+///
+/// let policy = GatewayPolicy::strict();
+/// let client = reqwest::Client::builder()
+///     .redirect(policy.redirect_policy())
+///     .dns_resolver(Arc::new(PublicResolver))
+///     .build()
+///     .unwrap();
+/// let config = CCIPReadConfig::default().gateway_policy(policy).client(client);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let mut rejected = None;
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| match check_ip(host, addr.ip()) {
+                    Ok(()) => true,
+                    Err(violation) => {
+                        rejected.get_or_insert(violation);
+                        false
+                    }
+                })
+                .collect();
+            if addrs.is_empty() {
+                let violation =
+                    rejected.unwrap_or_else(|| PolicyViolation::Unresolvable(host.to_string()));
+                return Err(violation.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Matches `host` against `pattern`, where `*.example.com` matches any subdomain of `example.com`
+pub(crate) fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.len() > domain.len() && host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+fn check_ip(host: &str, ip: IpAddr) -> Result<(), PolicyViolation> {
+    let private = match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(ip) => is_private_v4(ip),
+            None => is_private_v6(ip),
+        },
+    };
+    match private {
+        true => Err(PolicyViolation::PrivateAddress {
+            host: host.to_string(),
+            ip,
+        }),
+        false => Ok(()),
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        // "this network", 0.0.0.0/8
+        || a == 0
+        // shared address space, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking, 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // reserved, 240.0.0.0/4, broadcast included
+        || a >= 240
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    ip.is_multicast()
+        // unique local, fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link-local, fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // local-use NAT64, 64:ff9b:1::/48
+        || segments[..3] == [0x64, 0xff9b, 1]
+}
+
+/// The IPv4 address reached through an IPv6 address of a range embedding one
+///
+/// `::` and `::1` are IPv4-compatible addresses of `0.0.0.0/8`, so they are
+/// caught as such.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let v4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match ip.segments() {
+        // IPv4-mapped, ::ffff:0:0/96, and IPv4-compatible, ::/96
+        [0, 0, 0, 0, 0, 0xffff, high, low] | [0, 0, 0, 0, 0, 0, high, low] => Some(v4(high, low)),
+        // NAT64, 64:ff9b::/96
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(v4(high, low)),
+        // 6to4, 2002::/16
+        [0x2002, high, low, ..] => Some(v4(high, low)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_default_allows_everything() {
+        let policy = GatewayPolicy::default();
+
+        assert!(policy.check("http://127.0.0.1:8000/{data}").await.is_ok());
+        assert!(policy.check("https://gateway.example/").await.is_ok());
+        assert!(policy.check_url_count(100).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_https_only() {
+        let policy = GatewayPolicy::default().https_only(true);
+
+        assert_eq!(
+            policy.check("http://gateway.example/{data}").await,
+            Err(PolicyViolation::Scheme("http".to_string()))
+        );
+        assert!(policy.check("https://gateway.example/{data}").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_private_addresses() {
+        let policy = GatewayPolicy::default().block_private_ips(true);
+
+        for url in [
+            "https://169.254.169.254/latest/meta-data",
+            "https://127.0.0.1/",
+            "https://10.1.2.3/",
+            "https://192.168.0.1/",
+            "https://100.64.0.1/",
+            "https://[::1]/",
+            "https://[fd00::1]/",
+            "https://[fe80::1]/",
+            "https://[::ffff:127.0.0.1]/",
+            "https://[::]/",
+            "https://localhost/",
+            "https://0.1.2.3/",
+            "https://192.0.0.8/",
+            "https://198.18.0.1/",
+            "https://240.0.0.1/",
+            "https://255.255.255.255/",
+            "https://224.0.0.1/",
+            "https://[ff02::1]/",
+            // NAT64 of 169.254.169.254
+            "https://[64:ff9b::a9fe:a9fe]/",
+            "https://[64:ff9b:1::1]/",
+            // 6to4 of 10.0.0.1
+            "https://[2002:a00:1::1]/",
+            // IPv4-compatible 192.168.0.1
+            "https://[::c0a8:1]/",
+        ] {
+            assert!(
+                matches!(policy.check(url).await, Err(PolicyViolation::PrivateAddress { .. })),
+                "{}",
+                url
+            );
+        }
+        for url in [
+            "https://1.1.1.1/",
+            "https://[2606:4700:4700::1111]/",
+            "https://[64:ff9b::101:101]/",
+            "https://[2002:101:101::1]/",
+        ] {
+            assert!(policy.check(url).await.is_ok(), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_host_lists() {
+        let policy = GatewayPolicy::default()
+            .allow_host("*.ens.domains")
+            .allow_host("gateway.example")
+            .deny_host("bad.ens.domains");
+
+        assert!(policy.check("https://offchain.ens.domains/").await.is_ok());
+        assert!(policy.check("https://GATEWAY.example/").await.is_ok());
+        assert!(policy.check("https://ens.domains/").await.is_err());
+        assert!(policy.check("https://bad.ens.domains/").await.is_err());
+        assert!(policy.check("https://evilens.domains/").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_public_resolver() {
+        let resolve = |host: &str| PublicResolver.resolve(host.parse().unwrap());
+
+        let error = match resolve("localhost").await {
+            Ok(_) => panic!("localhost resolved"),
+            Err(error) => error,
+        };
+        assert!(matches!(
+            error.downcast_ref::<PolicyViolation>(),
+            Some(PolicyViolation::PrivateAddress { .. })
+        ));
+        assert!(resolve("1.1.1.1").await.is_ok());
+    }
+
+    #[test]
+    fn test_url_count() {
+        let policy = GatewayPolicy::default().max_urls(2);
+
+        assert!(policy.check_url_count(2).is_ok());
+        assert_eq!(
+            policy.check_url_count(3),
+            Err(PolicyViolation::TooManyURLs { count: 3, max: 2 })
+        );
+    }
+}
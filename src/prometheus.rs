@@ -0,0 +1,240 @@
+//! A [`MetricsSink`] exporting to a Prometheus registry, enabled by the `prometheus` feature
+use std::time::Duration;
+
+use ::prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, Result,
+};
+
+use crate::{metrics::MetricsSink, policy::host_matches};
+
+/// Buckets of the latency histograms, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Buckets of the `OffchainLookup` hops histogram
+const HOPS_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 3.0, 4.0];
+
+/// Records the measurements of the middleware as Prometheus metrics
+///
+/// | Metric | Type | Labels |
+/// |--------|------|--------|
+/// | `ccip_read_lookup_hops` | histogram | |
+/// | `ccip_read_gateway_requests_total` | counter | `host`, `status` |
+/// | `ccip_read_gateway_request_duration_seconds` | histogram | `host` |
+/// | `ccip_read_callback_duration_seconds` | histogram | |
+/// | `ccip_read_resolver_lookups_total` | counter | `found` |
+/// | `ccip_read_wildcard_checks_total` | counter | `supported` |
+/// | `ccip_read_cache_lookups_total` | counter | `cache`, `result` |
+/// | `ccip_read_errors_total` | counter | `operation`, `error` |
+///
+/// `status` is empty when a gateway did not respond.
+///
+/// Gateway URLs come from contract revert data, so by default the `host` label
+/// takes as many values as there are gateways in the wild, or as a malicious
+/// resolver wants. [`PrometheusMetrics::host_patterns`] bounds it to known hosts.
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::{prometheus::PrometheusMetrics, CCIPReadConfig};
+/// use prometheus::Registry;
+///
+/// let registry = Registry::new();
+/// let metrics = PrometheusMetrics::new(&registry)
+///     .unwrap()
+///     .host_patterns(["*.ens.domains"]);
+/// let config = CCIPReadConfig::default().metrics(metrics);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    lookup_hops: Histogram,
+    gateway_requests: IntCounterVec,
+    gateway_latency: HistogramVec,
+    callback_latency: Histogram,
+    resolver_lookups: IntCounterVec,
+    wildcard_checks: IntCounterVec,
+    cache_lookups: IntCounterVec,
+    errors: IntCounterVec,
+    host_patterns: Option<Vec<String>>,
+}
+
+impl PrometheusMetrics {
+    /// Creates the metrics and registers them in `registry`
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let metrics = Self {
+            lookup_hops: Histogram::with_opts(
+                HistogramOpts::new(
+                    "ccip_read_lookup_hops",
+                    "OffchainLookup reverts followed per call",
+                )
+                .buckets(HOPS_BUCKETS.to_vec()),
+            )?,
+            gateway_requests: IntCounterVec::new(
+                Opts::new("ccip_read_gateway_requests_total", "Gateway requests"),
+                &["host", "status"],
+            )?,
+            gateway_latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "ccip_read_gateway_request_duration_seconds",
+                    "Gateway request latency",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+                &["host"],
+            )?,
+            callback_latency: Histogram::with_opts(
+                HistogramOpts::new(
+                    "ccip_read_callback_duration_seconds",
+                    "OffchainLookup callback call latency",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            )?,
+            resolver_lookups: IntCounterVec::new(
+                Opts::new("ccip_read_resolver_lookups_total", "ENS resolver lookups"),
+                &["found"],
+            )?,
+            wildcard_checks: IntCounterVec::new(
+                Opts::new("ccip_read_wildcard_checks_total", "ENSIP-10 wildcard support checks"),
+                &["supported"],
+            )?,
+            cache_lookups: IntCounterVec::new(
+                Opts::new("ccip_read_cache_lookups_total", "Cache lookups"),
+                &["cache", "result"],
+            )?,
+            errors: IntCounterVec::new(
+                Opts::new("ccip_read_errors_total", "Failed operations by error variant"),
+                &["operation", "error"],
+            )?,
+            host_patterns: None,
+        };
+
+        registry.register(Box::new(metrics.lookup_hops.clone()))?;
+        registry.register(Box::new(metrics.gateway_requests.clone()))?;
+        registry.register(Box::new(metrics.gateway_latency.clone()))?;
+        registry.register(Box::new(metrics.callback_latency.clone()))?;
+        registry.register(Box::new(metrics.resolver_lookups.clone()))?;
+        registry.register(Box::new(metrics.wildcard_checks.clone()))?;
+        registry.register(Box::new(metrics.cache_lookups.clone()))?;
+        registry.register(Box::new(metrics.errors.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Labels gateway requests with the pattern their host matches, `*.example.com`
+    /// matching any subdomain, and with `other` for hosts matching none
+    pub fn host_patterns<I, T>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.host_patterns = Some(patterns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    fn host_label<'a>(&'a self, host: &'a str) -> &'a str {
+        match &self.host_patterns {
+            Some(patterns) => patterns
+                .iter()
+                .find(|pattern| host_matches(pattern, host))
+                .map_or("other", String::as_str),
+            None => host,
+        }
+    }
+}
+
+impl MetricsSink for PrometheusMetrics {
+    fn lookup_hops(&self, hops: u8) {
+        self.lookup_hops.observe(hops as f64);
+    }
+
+    fn gateway_request(&self, host: &str, status: Option<u16>, latency: Duration) {
+        let host = self.host_label(host);
+        let status = status.map(|status| status.to_string()).unwrap_or_default();
+        self.gateway_requests.with_label_values(&[host, &status]).inc();
+        self.gateway_latency
+            .with_label_values(&[host])
+            .observe(latency.as_secs_f64());
+    }
+
+    fn callback(&self, latency: Duration) {
+        self.callback_latency.observe(latency.as_secs_f64());
+    }
+
+    fn resolver_lookup(&self, found: bool) {
+        self.resolver_lookups.with_label_values(&[&found.to_string()]).inc();
+    }
+
+    fn wildcard_check(&self, supported: bool) {
+        self.wildcard_checks.with_label_values(&[&supported.to_string()]).inc();
+    }
+
+    fn cache(&self, cache: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.cache_lookups.with_label_values(&[cache, result]).inc();
+    }
+
+    fn error(&self, operation: &'static str, error: &'static str) {
+        self.errors.with_label_values(&[operation, error]).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::prometheus::{Encoder, TextEncoder};
+    use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest};
+    use ethers_providers::Provider;
+
+    use super::*;
+    use crate::{
+        test_utils::{lookup_via, CannedResponse, TestGateway},
+        CCIPReadConfig, CCIPReadMiddleware,
+    };
+
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let gateway = TestGateway::start(|request| match request.path.as_str() {
+            "/down" => CannedResponse::new(503, "down"),
+            _ => CannedResponse::data(&[0xca, 0xfe]),
+        })
+        .await;
+        let registry = Registry::new();
+        let (provider, mock) = Provider::mocked();
+        let middleware = CCIPReadMiddleware::new(provider)
+            .with_config(CCIPReadConfig::default().metrics(PrometheusMetrics::new(&registry).unwrap()));
+
+        let urls = vec![gateway.url("/down"), gateway.url("/up")];
+        lookup_via(&middleware, &mock, urls).await.unwrap();
+        // the mock has no more responses
+        let tx: TypedTransaction = TransactionRequest::new().to(Address::repeat_byte(1)).into();
+        middleware.call(&tx, None).await.unwrap_err();
+
+        let mut text = vec![];
+        TextEncoder::new().encode(&registry.gather(), &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let host = gateway.url("").trim_start_matches("http://").trim_end_matches('/').to_string();
+
+        for line in [
+            "ccip_read_lookup_hops_count 1".to_string(),
+            "ccip_read_lookup_hops_sum 1".to_string(),
+            format!(r#"ccip_read_gateway_requests_total{{host="{}",status="503"}} 1"#, host),
+            format!(r#"ccip_read_gateway_requests_total{{host="{}",status="200"}} 1"#, host),
+            "ccip_read_callback_duration_seconds_count 1".to_string(),
+            r#"ccip_read_errors_total{error="MiddlewareError",operation="call"} 1"#.to_string(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics::new(&registry)
+            .unwrap()
+            .host_patterns(["*.ens.domains"]);
+
+        for host in ["a.ens.domains", "b.ens.domains", "evil.example", "other.example"] {
+            metrics.gateway_request(host, Some(200), Duration::from_millis(10));
+        }
+
+        let counter = |host| metrics.gateway_requests.with_label_values(&[host, "200"]).get();
+        assert_eq!(counter("*.ens.domains"), 2);
+        assert_eq!(counter("other"), 2);
+    }
+}
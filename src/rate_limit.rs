@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A token bucket limiting the requests sent to each gateway host
+///
+/// Every host gets its own bucket of `burst` requests, refilled at
+/// `requests_per_second`. Requests wait for a token instead of failing. A 429
+/// response with a `Retry-After` header also holds back the other requests to
+/// that host until the given time: they fail right away, so that the lookup
+/// moves on to the next URL instead of waiting. A host is held back for at
+/// most an hour, whatever its `Retry-After`.
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::{rate_limit::RateLimit, CCIPReadConfig};
+///
+/// let config = CCIPReadConfig::default().rate_limit(RateLimit::new(20.0).burst(5));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Sustained number of requests per second to a single host
+    requests_per_second: f64,
+    /// Number of requests that can be sent at once to an idle host, at least 1
+    burst: u32,
+}
+
+impl RateLimit {
+    /// # Panics
+    ///
+    /// If `requests_per_second` is not a positive, finite number
+    pub fn new(requests_per_second: f64) -> Self {
+        assert!(
+            requests_per_second.is_finite() && requests_per_second > 0.0,
+            "requests_per_second must be positive and finite, got {}",
+            requests_per_second
+        );
+        Self {
+            requests_per_second,
+            burst: 1,
+        }
+    }
+
+    /// Sets the burst, a burst of 0 being the same as 1
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests_per_second
+    }
+
+    pub fn burst_size(&self) -> u32 {
+        self.burst
+    }
+}
+
+/// Longest a `Retry-After` header holds back a host
+const MAX_DEFER: Duration = Duration::from_secs(60 * 60);
+
+/// Most hosts with a bucket at once, beyond which idle buckets are dropped
+const MAX_HOSTS: usize = 1024;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Set by a `Retry-After` header
+    blocked_until: Option<Instant>,
+}
+
+/// Why a request can't be sent yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Wait {
+    /// No token left, one is available after the delay
+    Token(Duration),
+    /// The host asked to be left alone for the delay
+    Deferred(Duration),
+}
+
+/// The buckets of every gateway host, shared by the clones of a middleware
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    limit: Option<RateLimit>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: Option<RateLimit>) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Waits until a request can be sent to `host`, or returns how long the
+    /// host is still deferred by a `Retry-After` header
+    pub(crate) async fn acquire(&self, host: &str) -> Result<(), Duration> {
+        loop {
+            match self.try_acquire(host) {
+                None => return Ok(()),
+                Some(Wait::Token(wait)) => tokio::time::sleep(wait).await,
+                Some(Wait::Deferred(remaining)) => return Err(remaining),
+            }
+        }
+    }
+
+    /// Takes a token for `host`, or returns why it can't yet
+    fn try_acquire(&self, host: &str) -> Option<Wait> {
+        let limit = self.limit.as_ref()?;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.contains_key(host) && buckets.len() >= MAX_HOSTS {
+            Self::evict(&mut buckets, limit, now);
+        }
+        let bucket = buckets.entry(host.to_string()).or_insert_with(|| Bucket {
+            tokens: limit.burst as f64,
+            refilled_at: now,
+            blocked_until: None,
+        });
+
+        if let Some(until) = bucket.blocked_until.filter(|until| *until > now) {
+            return Some(Wait::Deferred(until - now));
+        }
+
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * limit.requests_per_second;
+        bucket.tokens = (bucket.tokens + refill).min(limit.burst as f64);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        let wait = Duration::try_from_secs_f64((1.0 - bucket.tokens) / limit.requests_per_second)
+            .unwrap_or(Duration::MAX);
+        Some(Wait::Token(wait))
+    }
+
+    /// Drops the buckets that are full and not deferred, as good as new ones,
+    /// or the least recently used one if there are none
+    fn evict(buckets: &mut HashMap<String, Bucket>, limit: &RateLimit, now: Instant) {
+        buckets.retain(|_, bucket| {
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * limit.requests_per_second;
+            let full = bucket.tokens + refill >= limit.burst as f64;
+            !full || bucket.blocked_until.is_some_and(|until| until > now)
+        });
+        if buckets.len() < MAX_HOSTS {
+            return;
+        }
+        let oldest = buckets
+            .iter()
+            .min_by_key(|(_, bucket)| bucket.refilled_at)
+            .map(|(host, _)| host.clone());
+        if let Some(oldest) = oldest {
+            buckets.remove(&oldest);
+        }
+    }
+
+    /// Holds back the requests to `host` for `retry_after`, up to [`MAX_DEFER`]
+    pub(crate) fn defer(&self, host: &str, retry_after: Duration) {
+        if self.limit.is_none() {
+            return;
+        }
+        let Some(until) = Instant::now().checked_add(retry_after.min(MAX_DEFER)) else {
+            return;
+        };
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(host) {
+            bucket.blocked_until = bucket.blocked_until.max(Some(until));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(Some(RateLimit::new(10.0).burst(2)));
+
+        assert_eq!(limiter.try_acquire("a.example"), None);
+        assert_eq!(limiter.try_acquire("a.example"), None);
+        let wait = match limiter.try_acquire("a.example") {
+            Some(Wait::Token(wait)) => wait,
+            other => panic!("expected to wait for a token, got {:?}", other),
+        };
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+
+        // buckets are per host
+        assert_eq!(limiter.try_acquire("b.example"), None);
+    }
+
+    #[tokio::test]
+    async fn test_defer() {
+        let limiter = RateLimiter::new(Some(RateLimit::new(1000.0)));
+
+        assert_eq!(limiter.try_acquire("a.example"), None);
+        limiter.defer("a.example", Duration::from_secs(5));
+
+        // a deferred host is given up on rather than waited for
+        let remaining = limiter.acquire("a.example").await.unwrap_err();
+        assert!(remaining > Duration::from_secs(4));
+        assert_eq!(limiter.acquire("b.example").await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_huge_retry_after_is_capped() {
+        let limiter = RateLimiter::new(Some(RateLimit::new(1000.0)));
+
+        assert_eq!(limiter.try_acquire("a.example"), None);
+        limiter.defer("a.example", Duration::from_secs(u64::MAX));
+
+        let remaining = limiter.acquire("a.example").await.unwrap_err();
+        assert!(remaining <= MAX_DEFER && remaining > MAX_DEFER - Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_limit_is_validated() {
+        assert_eq!(RateLimit::new(1.0).burst(0).burst_size(), 1);
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(std::panic::catch_unwind(|| RateLimit::new(rate)).is_err(), "{}", rate);
+        }
+
+        // a rate too low for a `Duration` waits forever rather than panicking
+        let limiter = RateLimiter::new(Some(RateLimit::new(f64::MIN_POSITIVE)));
+        assert_eq!(limiter.try_acquire("a.example"), None);
+        assert_eq!(limiter.try_acquire("a.example"), Some(Wait::Token(Duration::MAX)));
+    }
+
+    #[test]
+    fn test_idle_buckets_are_dropped() {
+        let limiter = RateLimiter::new(Some(RateLimit::new(0.001)));
+
+        for host in 0..MAX_HOSTS {
+            assert_eq!(limiter.try_acquire(&format!("{}.example", host)), None);
+        }
+        // every bucket is empty, the least recently used one makes room
+        assert_eq!(limiter.try_acquire("new.example"), None);
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_HOSTS);
+        assert!(buckets.contains_key("new.example"));
+        drop(buckets);
+
+        let limiter = RateLimiter::new(Some(RateLimit::new(1e9)));
+        for host in 0..MAX_HOSTS {
+            assert_eq!(limiter.try_acquire(&format!("{}.example", host)), None);
+        }
+        // refilled buckets are all dropped
+        assert_eq!(limiter.try_acquire("new.example"), None);
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = RateLimiter::new(None);
+
+        for _ in 0..100 {
+            assert_eq!(limiter.try_acquire("a.example"), None);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Retries of a single gateway URL on connection errors, timeouts and 5xx responses
+///
+/// The delay before retry `n` is `initial_backoff * 2^n`, capped at
+/// `max_backoff`, of which a random fraction of up to a half is removed so that
+/// clients do not retry in lockstep. When a deadline is configured, no retry is
+/// attempted if its delay would run past the deadline.
+///
+/// A `Retry-After` header sets the least delay of the next retry. When it asks
+/// for more than `max_backoff`, the URL is not retried and the next one is tried.
+///
+/// Retries are disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use ethers_ccip_read::{retry::RetryPolicy, CCIPReadConfig};
+///
+/// let config = CCIPReadConfig::default()
+///     .deadline(Duration::from_secs(10))
+///     .retry(RetryPolicy::new(3).initial_backoff(Duration::from_millis(250)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries per gateway URL, after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// The delay before retry `retry` (starting at 0), including jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(10)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1));
+
+        for (retry, max) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000), (40, 1000)] {
+            let backoff = policy.backoff(retry);
+            let max = Duration::from_millis(max);
+            assert!(backoff <= max && backoff >= max / 2, "{} {:?}", retry, backoff);
+        }
+    }
+}
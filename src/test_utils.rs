@@ -0,0 +1,304 @@
+//! Helpers shared by the unit tests: a mocked JSON-RPC provider and a minimal
+//! HTTP/1.1 server standing in for CCIP-Read gateways.
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ethers_core::types::{
+    transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest,
+};
+use ethers_providers::{JsonRpcError, MockProvider, MockResponse, Provider};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{error::CCIPMiddlewareError, utils::offchain_lookup::OffchainLookup, CCIPReadMiddleware};
+
+/// A JSON-RPC error carrying `data` as revert data
+pub fn revert(data: &[u8]) -> MockResponse {
+    MockResponse::Error(JsonRpcError {
+        code: 3,
+        message: "execution reverted".to_string(),
+        data: Some(serde_json::Value::String(format!("0x{}", hex::encode(data)))),
+    })
+}
+
+/// A successful `eth_call` result
+pub fn success(data: &[u8]) -> MockResponse {
+    MockResponse::Value(serde_json::to_value(Bytes::from(data.to_vec())).unwrap())
+}
+
+/// Queues `responses` so that they are returned in order
+pub fn push_responses(mock: &MockProvider, responses: Vec<MockResponse>) {
+    for response in responses.into_iter().rev() {
+        mock.push_response(response);
+    }
+}
+
+/// Calls a contract reverting with an `OffchainLookup` of `urls` through
+/// `middleware`, its callback returning `0x01`
+pub async fn lookup_via(
+    middleware: &CCIPReadMiddleware<Provider<MockProvider>>,
+    mock: &MockProvider,
+    urls: Vec<String>,
+) -> Result<Bytes, CCIPMiddlewareError<Provider<MockProvider>>> {
+    let sender = Address::repeat_byte(1);
+    let lookup = OffchainLookup {
+        sender,
+        urls,
+        ..Default::default()
+    };
+    let tx: TypedTransaction = TransactionRequest::new().to(sender).into();
+    push_responses(mock, vec![revert(&lookup.encode()), success(&[1])]);
+
+    middleware.call(&tx, None).await
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CannedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl CannedResponse {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// A successful gateway response returning `data`
+    pub fn data(data: &[u8]) -> Self {
+        Self::new(200, format!(r#"{{"data":"0x{}"}}"#, hex::encode(data)))
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&RecordedRequest) -> CannedResponse + Send + Sync;
+
+/// A keep-alive HTTP/1.1 server on localhost, answering every request with `handler`
+pub struct TestGateway {
+    pub addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl TestGateway {
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&RecordedRequest) -> CannedResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let (accepted, seen) = (connections.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(stream, handler.clone(), seen.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            connections,
+            requests,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Number of TCP connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve(stream: TcpStream, handler: Arc<Handler>, requests: Arc<Mutex<Vec<RecordedRequest>>>) {
+    let mut stream = BufReader::new(stream);
+
+    loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+
+        let length = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        if stream.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        let request = RecordedRequest {
+            method,
+            path,
+            headers,
+            body: String::from_utf8_lossy(&body).to_string(),
+        };
+        let response = handler(&request);
+        requests.lock().unwrap().push(request);
+
+        let mut head = format!(
+            "HTTP/1.1 {} Gateway\r\ncontent-type: application/json\r\ncontent-length: {}\r\n",
+            response.status,
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        let stream = stream.get_mut();
+        if stream.write_all(head.as_bytes()).await.is_err()
+            || stream.write_all(response.body.as_bytes()).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+type SpanFields = Vec<(String, String)>;
+
+/// A single-threaded tracing subscriber recording the name and fields of every span
+#[derive(Debug, Clone, Default)]
+pub struct SpanRecorder {
+    spans: Arc<Mutex<Vec<(&'static tracing::Metadata<'static>, SpanFields)>>>,
+    entered: Arc<Mutex<Vec<tracing::span::Id>>>,
+}
+
+impl SpanRecorder {
+    /// The fields of every span named `name`, in creation order
+    pub fn spans(&self, name: &str) -> Vec<SpanFields> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(span, _)| span.name() == name)
+            .map(|(_, fields)| fields.clone())
+            .collect()
+    }
+
+    /// The value of `field` in the first span named `name`
+    pub fn field(&self, name: &str, field: &str) -> Option<String> {
+        self.spans(name)
+            .first()?
+            .iter()
+            .find(|(key, _)| key == field)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut SpanFields);
+
+impl tracing::field::Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut fields = vec![];
+        span.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata(), fields));
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut FieldVisitor(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, _: &tracing::Event<'_>) {}
+
+    fn enter(&self, span: &tracing::span::Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _: &tracing::span::Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> tracing_core::span::Current {
+        match self.entered.lock().unwrap().last() {
+            Some(span) => {
+                let metadata = self.spans.lock().unwrap()[span.into_u64() as usize - 1].0;
+                tracing_core::span::Current::new(span.clone(), metadata)
+            }
+            None => tracing_core::span::Current::none(),
+        }
+    }
+}
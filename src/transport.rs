@@ -0,0 +1,177 @@
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use ethers_core::types::{Address, Bytes};
+use reqwest::header::HeaderMap;
+use thiserror::Error;
+#[cfg(feature = "otel")]
+use tracing::Instrument;
+
+use crate::{host_options::HostOptions, utils::gateway_request::GatewayRequest};
+
+/// Error returned by a [`GatewayTransport`]
+pub type TransportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Raw response of a CCIP-Read gateway
+#[derive(Debug, Clone, Default)]
+pub struct GatewayResponse {
+    /// HTTP status code, or its equivalent for non-HTTP transports
+    pub status: u16,
+    /// Response headers, such as `Retry-After`
+    pub headers: HeaderMap,
+    /// Response body, expected to be `{"data": "0x..."}` on success
+    pub body: Bytes,
+}
+
+/// Returned by a transport when a response body is larger than its limit
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Response body exceeds {limit} bytes")]
+pub struct ResponseTooLarge {
+    /// HTTP status of the response
+    pub status: u16,
+    /// The maximum body size, in bytes
+    pub limit: usize,
+}
+
+/// Sends the offchain requests of an `OffchainLookup` to a gateway
+///
+/// [`ReqwestTransport`] is used unless another transport is set with
+/// [`CCIPReadMiddleware::with_transport`](crate::CCIPReadMiddleware::with_transport),
+/// which allows serving gateways in-memory, through a proxy, or from local handlers.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait GatewayTransport: Debug + Send + Sync {
+    /// Sends `calldata` on behalf of `sender` to the gateway `url` template
+    async fn request(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        url: &str,
+    ) -> Result<GatewayResponse, TransportError>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T: GatewayTransport + ?Sized> GatewayTransport for Arc<T> {
+    async fn request(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        url: &str,
+    ) -> Result<GatewayResponse, TransportError> {
+        self.as_ref().request(sender, calldata, url).await
+    }
+}
+
+/// The default HTTP transport, backed by a shared `reqwest::Client`
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    max_response_size: Option<usize>,
+    /// Clients dedicated to the hosts with their own options
+    hosts: Vec<(HostOptions, reqwest::Client)>,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            max_response_size: None,
+            hosts: vec![],
+        }
+    }
+
+    /// Sends the requests to hosts matching `options` through `client`, with the
+    /// headers of `options`
+    pub fn host(mut self, options: HostOptions, client: reqwest::Client) -> Self {
+        self.hosts.push((options, client));
+        self
+    }
+
+    /// Stops reading responses larger than `max_response_size` bytes with a [`ResponseTooLarge`] error
+    pub fn max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl GatewayTransport for ReqwestTransport {
+    async fn request(
+        &self,
+        sender: Address,
+        calldata: &[u8],
+        url: &str,
+    ) -> Result<GatewayResponse, TransportError> {
+        let request = GatewayRequest::new(url, sender, calldata)?;
+        #[cfg(feature = "otel")]
+        {
+            let span = crate::otel::client_span(&request);
+            let result = self.send(request).instrument(span.clone()).await;
+            crate::otel::record_result(&span, &result);
+            result
+        }
+        #[cfg(not(feature = "otel"))]
+        self.send(request).await
+    }
+}
+
+impl ReqwestTransport {
+    async fn send(&self, request: GatewayRequest) -> Result<GatewayResponse, TransportError> {
+        let url = request.url();
+        let (client, headers) = match self.hosts.iter().find(|(options, _)| options.applies_to(url)) {
+            Some((options, client)) => (client, options.headers_for(request.url())),
+            None => (&self.client, HeaderMap::new()),
+        };
+
+        let request = match request {
+            GatewayRequest::Get { url } => client.get(url),
+            GatewayRequest::Post { url, body } => client.post(url).json(&body),
+        };
+        let request = request.headers(headers);
+        #[cfg(feature = "otel")]
+        let request = request.headers(crate::otel::context_headers());
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let limit = match self.max_response_size {
+            Some(limit) => limit,
+            None => {
+                return Ok(GatewayResponse {
+                    status,
+                    headers,
+                    body: response.bytes().await?.into(),
+                })
+            }
+        };
+
+        Ok(GatewayResponse {
+            status,
+            headers,
+            body: read_body::<TransportError>(response, limit).await?,
+        })
+    }
+}
+
+/// Reads the body of `response`, failing with [`ResponseTooLarge`] once it
+/// exceeds `limit` bytes
+pub(crate) async fn read_body<E>(mut response: reqwest::Response, limit: usize) -> Result<Bytes, E>
+where
+    E: From<ResponseTooLarge> + From<reqwest::Error>,
+{
+    // read chunk by chunk so that an oversized body is never fully buffered
+    let status = response.status().as_u16();
+    if response.content_length().unwrap_or_default() > limit as u64 {
+        return Err(ResponseTooLarge { status, limit }.into());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(ResponseTooLarge { status, limit }.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.into())
+}
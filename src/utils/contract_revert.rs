@@ -0,0 +1,143 @@
+use std::{
+    convert::TryInto,
+    fmt::{self, Display},
+};
+
+use ethers_core::{
+    abi::{self, ParamType, Token},
+    types::{Bytes, Selector, U256},
+};
+
+/// The revert of a call that is not an `OffchainLookup`
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::utils::contract_revert::ContractRevert;
+/// use ethers_core::abi::{self, Token};
+///
+/// let data = [
+///     &ContractRevert::ERROR_SELECTOR[..],
+///     &abi::encode(&[Token::String("not found".to_string())]),
+/// ]
+/// .concat();
+///
+/// assert_eq!(ContractRevert::decode(&data), ContractRevert::Error("not found".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContractRevert {
+    /// `Error(string)`, from `require` or `revert` with a message
+    Error(String),
+    /// `Panic(uint256)`, from a failed `assert`, an overflow, a division by zero...
+    Panic(U256),
+    /// A custom error, `data` including the selector
+    Custom { selector: Selector, data: Bytes },
+    /// Revert data too short for a selector, usually empty from a bare `revert()`
+    Unknown(Bytes),
+}
+
+impl ContractRevert {
+    /// selector("Error(string)")
+    pub const ERROR_SELECTOR: Selector = [0x08, 0xc3, 0x79, 0xa0];
+
+    /// selector("Panic(uint256)")
+    pub const PANIC_SELECTOR: Selector = [0x4e, 0x48, 0x7b, 0x71];
+
+    /// Decodes revert data, malformed `Error` and `Panic` data being kept as custom errors
+    pub fn decode(data: &[u8]) -> Self {
+        let selector: Selector = match data.get(..4) {
+            Some(selector) => selector.try_into().unwrap(),
+            None => return ContractRevert::Unknown(data.to_vec().into()),
+        };
+
+        let tokens = match selector {
+            Self::ERROR_SELECTOR => abi::decode(&[ParamType::String], &data[4..]),
+            Self::PANIC_SELECTOR => abi::decode(&[ParamType::Uint(256)], &data[4..]),
+            _ => Err(abi::Error::InvalidData),
+        };
+        match tokens.as_deref() {
+            Ok([Token::String(reason)]) => ContractRevert::Error(reason.clone()),
+            Ok([Token::Uint(code)]) => ContractRevert::Panic(*code),
+            _ => ContractRevert::Custom {
+                selector,
+                data: data.to_vec().into(),
+            },
+        }
+    }
+
+    /// What the code of a `Panic` means, following the Solidity documentation
+    pub fn panic_reason(code: U256) -> Option<&'static str> {
+        if code > U256::from(u8::MAX) {
+            return None;
+        }
+        Some(match code.as_u32() {
+            0x00 => "generic compiler panic",
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum value",
+            0x22 => "invalid storage byte array encoding",
+            0x31 => "pop on an empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to an uninitialized function",
+            _ => return None,
+        })
+    }
+}
+
+impl Display for ContractRevert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContractRevert::Error(reason) => write!(f, "{}", reason),
+            ContractRevert::Panic(code) => match Self::panic_reason(*code) {
+                Some(reason) => write!(f, "panic 0x{:x} ({})", code, reason),
+                None => write!(f, "panic 0x{:x}", code),
+            },
+            ContractRevert::Custom { selector, .. } => {
+                write!(f, "custom error 0x{}", hex::encode(selector))
+            }
+            ContractRevert::Unknown(data) if data.is_empty() => write!(f, "no revert data"),
+            ContractRevert::Unknown(data) => write!(f, "revert data {}", data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revert_data(selector: Selector, tokens: &[Token]) -> Vec<u8> {
+        [&selector[..], &abi::encode(tokens)].concat()
+    }
+
+    #[test]
+    fn test_decode_standard_reverts() {
+        let data = revert_data(ContractRevert::PANIC_SELECTOR, &[Token::Uint(0x11.into())]);
+        let panic = ContractRevert::decode(&data);
+        assert_eq!(panic, ContractRevert::Panic(0x11.into()));
+        assert_eq!(panic.to_string(), "panic 0x11 (arithmetic overflow or underflow)");
+
+        let data = revert_data(ContractRevert::ERROR_SELECTOR, &[Token::String("nope".into())]);
+        assert_eq!(ContractRevert::decode(&data).to_string(), "nope");
+    }
+
+    #[test]
+    fn test_decode_custom_reverts() {
+        let data = revert_data([0xde, 0xad, 0xbe, 0xef], &[Token::Uint(1.into())]);
+        assert_eq!(
+            ContractRevert::decode(&data),
+            ContractRevert::Custom {
+                selector: [0xde, 0xad, 0xbe, 0xef],
+                data: data.clone().into(),
+            }
+        );
+
+        // a truncated `Error(string)` is kept as is
+        let data = &ContractRevert::ERROR_SELECTOR[..];
+        assert!(matches!(ContractRevert::decode(data), ContractRevert::Custom { .. }));
+
+        assert_eq!(ContractRevert::decode(&[]), ContractRevert::Unknown(Bytes::new()));
+        assert_eq!(ContractRevert::decode(&[]).to_string(), "no revert data");
+    }
+}
@@ -3,13 +3,19 @@ use ethers_core::{
     types::Bytes,
 };
 
+use crate::error::DecodeError;
+
 /// infallible conversion of Bytes to Address/String
 ///
 /// # Panics
 ///
 /// If the provided bytes were not an interpretation of an address
 pub fn decode_bytes<T: Detokenize>(param: ParamType, bytes: Bytes) -> T {
-    let tokens = abi::decode(&[param], bytes.as_ref())
-        .expect("could not abi-decode bytes to address tokens");
-    T::from_tokens(tokens).expect("could not parse tokens as address")
+    try_decode_bytes(param, bytes).expect("could not abi-decode bytes to address tokens")
+}
+
+/// fallible conversion of Bytes to Address/String, for data returned by untrusted contracts
+pub fn try_decode_bytes<T: Detokenize>(param: ParamType, bytes: Bytes) -> Result<T, DecodeError> {
+    let tokens = abi::decode(&[param], bytes.as_ref())?;
+    Ok(T::from_tokens(tokens)?)
 }
@@ -16,7 +16,7 @@
 /// # Example
 ///
 /// ```
-/// use ethers_ccip_read::utils::{dns_encode};
+/// use ethers_ccip_read::utils::dns_encode::dns_encode;
 ///
 /// let encoded = dns_encode("tanrikulu.eth").unwrap();
 /// assert_eq!(encoded, vec![9, b't', b'a', b'n', b'r', b'i', b'k', b'u', b'l', b'u', 3, b'e', b't', b'h', 0]);
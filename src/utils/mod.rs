@@ -1,3 +1,7 @@
+pub mod contract_revert;
 pub mod decode_bytes;
 pub mod dns_encode;
+pub mod gateway_request;
+pub mod offchain_lookup;
+pub mod revert_data;
 pub mod selectors;
@@ -0,0 +1,231 @@
+use std::{borrow::Cow, convert::TryInto};
+
+use ethers_contract::EthError;
+use ethers_core::{
+    abi::{self, AbiDecode, AbiEncode, AbiError, InvalidOutputType, ParamType, Token, Tokenizable},
+    types::{Address, Bytes, Selector},
+};
+
+use crate::error::DecodeError;
+
+/// The `OffchainLookup` error defined by [EIP-3668](https://eips.ethereum.org/EIPS/eip-3668)
+///
+/// ```solidity
+/// error OffchainLookup(address sender, string[] urls, bytes callData, bytes4 callbackFunction, bytes extraData);
+/// ```
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::utils::offchain_lookup::OffchainLookup;
+/// use ethers_core::types::{Address, Bytes};
+///
+/// let lookup = OffchainLookup {
+///     sender: Address::repeat_byte(1),
+///     urls: vec!["https://gateway.example/{sender}/{data}.json".to_string()],
+///     call_data: Bytes::from(vec![1, 2, 3]),
+///     callback_function: [0xb4, 0xa8, 0x58, 0x01],
+///     extra_data: Bytes::default(),
+/// };
+///
+/// let revert_data = lookup.encode();
+/// assert_eq!(OffchainLookup::decode(&revert_data).unwrap(), lookup);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OffchainLookup {
+    pub sender: Address,
+    pub urls: Vec<String>,
+    pub call_data: Bytes,
+    pub callback_function: Selector,
+    pub extra_data: Bytes,
+}
+
+impl OffchainLookup {
+    /// selector("OffchainLookup(address,string[],bytes,bytes4,bytes)")
+    pub const SELECTOR: Selector = [0x55, 0x6f, 0x18, 0x30];
+
+    /// selector plus the five head words of the tuple
+    const MIN_LEN: usize = 4 + 5 * 32;
+
+    fn param_types() -> [ParamType; 5] {
+        [
+            ParamType::Address,                            // 'address'
+            ParamType::Array(Box::new(ParamType::String)), // 'string[]'
+            ParamType::Bytes,                              // 'bytes'
+            ParamType::FixedBytes(4),                      // 'bytes4'
+            ParamType::Bytes,                              // 'bytes'
+        ]
+    }
+
+    /// Returns true if `data` starts with the `OffchainLookup` selector
+    pub fn matches(data: &[u8]) -> bool {
+        data.starts_with(&Self::SELECTOR)
+    }
+
+    /// Decodes `OffchainLookup` revert data, including the selector
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        if data.len() < Self::MIN_LEN {
+            return Err(DecodeError::ShortData {
+                expected: Self::MIN_LEN,
+                actual: data.len(),
+            });
+        }
+        if !Self::matches(data) {
+            return Err(DecodeError::InvalidAbi(abi::Error::InvalidData));
+        }
+        if data.len() % 32 != 4 {
+            return Err(DecodeError::MisalignedData(data.len()));
+        }
+
+        let tokens = abi::decode(&Self::param_types(), &data[4..])?;
+        Ok(Self::from_token(Token::Tuple(tokens))?)
+    }
+
+    /// Encodes the lookup as revert data, including the selector
+    pub fn encode(&self) -> Bytes {
+        let tokens = self.clone().into_tokens();
+        [&Self::SELECTOR[..], &abi::encode(&tokens)].concat().into()
+    }
+
+    fn into_tokens(self) -> Vec<Token> {
+        vec![
+            Token::Address(self.sender),
+            Token::Array(self.urls.into_iter().map(Token::String).collect()),
+            Token::Bytes(self.call_data.to_vec()),
+            Token::FixedBytes(self.callback_function.to_vec()),
+            Token::Bytes(self.extra_data.to_vec()),
+        ]
+    }
+
+    /// Builds the calldata for `callbackFunction(bytes response, bytes extraData)`
+    pub fn callback_calldata(&self, response: &[u8]) -> Bytes {
+        let tokens = [
+            Token::Bytes(response.to_vec()),
+            Token::Bytes(self.extra_data.to_vec()),
+        ];
+        [&self.callback_function[..], &abi::encode(&tokens)]
+            .concat()
+            .into()
+    }
+}
+
+impl Tokenizable for OffchainLookup {
+    fn from_token(token: Token) -> Result<Self, InvalidOutputType> {
+        if let Token::Tuple(tokens) = token {
+            if let [
+                Token::Address(sender),
+                Token::Array(urls),
+                Token::Bytes(call_data),
+                Token::FixedBytes(callback_function),
+                Token::Bytes(extra_data),
+            ] = tokens.as_slice()
+            {
+                let urls = urls
+                    .iter()
+                    .map(|url| match url {
+                        Token::String(url) => Ok(url.clone()),
+                        other => Err(InvalidOutputType(format!("Expected string url, got {:?}", other))),
+                    })
+                    .collect::<Result<_, _>>()?;
+                let callback_function = callback_function.as_slice().try_into().map_err(|_| {
+                    InvalidOutputType(format!("Expected bytes4, got {:?}", callback_function))
+                })?;
+
+                return Ok(Self {
+                    sender: *sender,
+                    urls,
+                    call_data: call_data.clone().into(),
+                    callback_function,
+                    extra_data: extra_data.clone().into(),
+                });
+            }
+            return Err(InvalidOutputType(format!("Expected OffchainLookup tuple, got {:?}", tokens)));
+        }
+        Err(InvalidOutputType(format!("Expected tuple, got {:?}", token)))
+    }
+
+    fn into_token(self) -> Token {
+        Token::Tuple(self.into_tokens())
+    }
+}
+
+impl AbiDecode for OffchainLookup {
+    fn decode(bytes: impl AsRef<[u8]>) -> Result<Self, AbiError> {
+        OffchainLookup::decode(bytes.as_ref()).map_err(|e| match e {
+            DecodeError::InvalidAbi(e) => AbiError::DecodingError(e),
+            DecodeError::InvalidOutputType(e) => AbiError::DetokenizationError(e),
+            _ => AbiError::DecodingError(abi::Error::InvalidData),
+        })
+    }
+}
+
+impl AbiEncode for OffchainLookup {
+    fn encode(self) -> Vec<u8> {
+        OffchainLookup::encode(&self).to_vec()
+    }
+}
+
+impl EthError for OffchainLookup {
+    fn error_name() -> Cow<'static, str> {
+        Cow::Borrowed("OffchainLookup")
+    }
+
+    fn abi_signature() -> Cow<'static, str> {
+        Cow::Borrowed("OffchainLookup(address,string[],bytes,bytes4,bytes)")
+    }
+
+    fn selector() -> Selector {
+        Self::SELECTOR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::utils::id;
+
+    fn lookup() -> OffchainLookup {
+        OffchainLookup {
+            sender: Address::repeat_byte(0x11),
+            urls: vec![
+                "https://a.example/{sender}/{data}.json".to_string(),
+                "https://b.example/".to_string(),
+            ],
+            call_data: Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]),
+            callback_function: [0xb4, 0xa8, 0x58, 0x01],
+            extra_data: Bytes::from(vec![0x01; 40]),
+        }
+    }
+
+    #[test]
+    fn test_selector() {
+        assert_eq!(OffchainLookup::SELECTOR, id(OffchainLookup::abi_signature()));
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let encoded = lookup().encode();
+
+        assert!(OffchainLookup::matches(&encoded));
+        assert_eq!(OffchainLookup::decode(&encoded).unwrap(), lookup());
+        assert_eq!(<OffchainLookup as AbiDecode>::decode(&encoded).unwrap(), lookup());
+        assert_eq!(EthError::decode_with_selector(&encoded), Some(lookup()));
+    }
+
+    #[test]
+    fn test_decode_wrong_selector() {
+        let mut encoded = lookup().encode().to_vec();
+        encoded[0] = 0;
+
+        assert!(OffchainLookup::decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_callback_calldata() {
+        let calldata = lookup().callback_calldata(&[0xca, 0xfe]);
+        let tokens = abi::decode(&[ParamType::Bytes, ParamType::Bytes], &calldata[4..]).unwrap();
+
+        assert_eq!(calldata[..4], lookup().callback_function);
+        assert_eq!(tokens, vec![Token::Bytes(vec![0xca, 0xfe]), Token::Bytes(vec![0x01; 40])]);
+    }
+}
@@ -0,0 +1,161 @@
+use ethers_core::types::Bytes;
+use ethers_providers::JsonRpcError;
+use serde_json::Value;
+
+use crate::error::DecodeError;
+
+/// Keys under which nodes and proxies nest the revert data, most specific first
+const DATA_KEYS: [&str; 5] = ["data", "originalError", "error", "return", "result"];
+
+/// Marker after which a revert message may carry the revert data
+const MESSAGE_MARKER: &str = "reverted:";
+
+/// Finds the revert data of a failed `eth_call` in a JSON-RPC error
+///
+/// Nodes and RPC proxies put it in different places:
+///
+/// - a hex string in `data`, e.g. Geth, Erigon, Anvil
+/// - after a prefix in `data`, e.g. `"Reverted 0x..."` from Nethermind
+/// - in a nested object, e.g. `data.data` from Hardhat, `data.originalError.data`
+///   from wallet proxies, or `data.<tx hash>.return` from Ganache
+/// - right after `reverted:` in the message, when a proxy dropped `data`
+///
+/// Only these keys are followed, and hex out of a message must be a selector
+/// followed by whole 32 byte words, so that hashes and addresses of other
+/// errors are never taken for revert data.
+///
+/// Returns `None` when the error carries no revert data, and an error when
+/// the data found is not valid hex.
+///
+/// # Example
+///
+/// ```
+/// use ethers_ccip_read::utils::revert_data::extract_revert_data;
+/// use ethers_providers::JsonRpcError;
+///
+/// let error = JsonRpcError {
+///     code: -32015,
+///     message: "VM execution error.".to_string(),
+///     data: Some("Reverted 0x556f1830".into()),
+/// };
+///
+/// assert_eq!(extract_revert_data(&error).unwrap().unwrap().to_vec(), [0x55, 0x6f, 0x18, 0x30]);
+/// ```
+pub fn extract_revert_data(error: &JsonRpcError) -> Result<Option<Bytes>, DecodeError> {
+    let data = match error.data.as_ref().and_then(find_in_value) {
+        Some(data) => Some(data),
+        None => hex_in_message(&error.message),
+    };
+
+    data.map(|data| {
+        let data = data.strip_prefix("0x").unwrap_or(data);
+        Ok(hex::decode(data)?.into())
+    })
+    .transpose()
+}
+
+fn find_in_value(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(data) => find_in_string(data),
+        Value::Object(object) => DATA_KEYS
+            .iter()
+            .filter_map(|key| object.get(*key))
+            // Ganache keys the revert by the hash of the call
+            .chain(object.iter().filter(|(key, _)| is_hash(key)).map(|(_, value)| value))
+            .find_map(find_in_value),
+        _ => None,
+    }
+}
+
+/// A `0x` string is revert data as is, otherwise only Nethermind's `Reverted 0x...`
+fn find_in_string(data: &str) -> Option<&str> {
+    let data = data.trim().trim_matches('"');
+    if data.starts_with("0x") && !data.contains(char::is_whitespace) {
+        return Some(data);
+    }
+    data.strip_prefix("Reverted ").and_then(aligned_hex)
+}
+
+/// The hex right after `reverted:` in a message, e.g. `execution reverted: 0x...`
+fn hex_in_message(message: &str) -> Option<&str> {
+    // lowercasing ASCII keeps the byte offsets
+    let start = message.to_ascii_lowercase().find(MESSAGE_MARKER)? + MESSAGE_MARKER.len();
+    aligned_hex(message[start..].trim_start())
+}
+
+/// The leading hex of `text` if it is a selector followed by whole 32 byte words
+fn aligned_hex(text: &str) -> Option<&str> {
+    let digits = text.strip_prefix("0x")?;
+    let len = digits
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .unwrap_or(digits.len());
+    // in bytes, `len % 32 == 4`
+    (len % 64 == 8).then(|| &text[..2 + len])
+}
+
+fn is_hash(key: &str) -> bool {
+    key.len() == 66 && key.starts_with("0x") && key[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Error("not found")`, the revert data of every fixture
+    const REVERT_DATA: &str = "0x08c379a0000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000096e6f7420666f756e640000000000000000000000000000000000000000000000";
+
+    fn fixture(json: &str) -> JsonRpcError {
+        let response: Value = serde_json::from_str(json).unwrap();
+        serde_json::from_value(response["error"].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_provider_error_shapes() {
+        let fixtures = [
+            ("geth", include_str!("fixtures/revert_data/geth.json")),
+            ("erigon", include_str!("fixtures/revert_data/erigon.json")),
+            ("nethermind", include_str!("fixtures/revert_data/nethermind.json")),
+            ("anvil", include_str!("fixtures/revert_data/anvil.json")),
+            ("hardhat", include_str!("fixtures/revert_data/hardhat.json")),
+            ("ganache", include_str!("fixtures/revert_data/ganache.json")),
+            ("wallet_proxy", include_str!("fixtures/revert_data/wallet_proxy.json")),
+            ("quoted_proxy", include_str!("fixtures/revert_data/quoted_proxy.json")),
+            ("message_only", include_str!("fixtures/revert_data/message_only.json")),
+        ];
+        let expected: Bytes = REVERT_DATA.parse().unwrap();
+
+        for (name, json) in fixtures.iter() {
+            let data = extract_revert_data(&fixture(json)).unwrap();
+            assert_eq!(data.as_ref(), Some(&expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_errors_without_revert_data() {
+        let fixtures = [
+            include_str!("fixtures/revert_data/no_data.json"),
+            include_str!("fixtures/revert_data/insufficient_funds.json"),
+            include_str!("fixtures/revert_data/tx_hash.json"),
+            include_str!("fixtures/revert_data/access_control.json"),
+        ];
+
+        for json in fixtures.iter() {
+            assert_eq!(extract_revert_data(&fixture(json)).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_empty_and_invalid_revert_data() {
+        let error = |data: &str| JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: Some(data.into()),
+        };
+
+        assert_eq!(extract_revert_data(&error("0x")).unwrap(), Some(Bytes::new()));
+        assert!(matches!(
+            extract_revert_data(&error("0x556f18zz")),
+            Err(DecodeError::InvalidHex(_))
+        ));
+    }
+}